@@ -0,0 +1 @@
+ALTER TABLE apps ADD COLUMN enable_client_events BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE apps ADD COLUMN webhook_url TEXT;
@@ -0,0 +1 @@
+ALTER TABLE apps ADD COLUMN enable_client_event_webhooks BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,23 @@
+CREATE TABLE webhook_outbox
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    app_id          bigint NOT NULL,
+    body            TEXT NOT NULL,
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at bigint NOT NULL,
+    last_error      TEXT
+);
+
+CREATE INDEX webhook_outbox_next_attempt_at ON webhook_outbox (next_attempt_at);
+
+CREATE TABLE webhook_dead_letters
+(
+    id         INTEGER PRIMARY KEY NOT NULL,
+    app_id     bigint NOT NULL,
+    body       TEXT NOT NULL,
+    attempts   INTEGER NOT NULL,
+    last_error TEXT,
+    failed_at  bigint NOT NULL
+);
+
+CREATE INDEX webhook_dead_letters_app_id ON webhook_dead_letters (app_id);
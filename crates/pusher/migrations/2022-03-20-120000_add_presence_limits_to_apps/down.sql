@@ -0,0 +1,2 @@
+ALTER TABLE apps DROP COLUMN max_presence_user_info_size;
+ALTER TABLE apps DROP COLUMN max_presence_members;
@@ -0,0 +1,2 @@
+ALTER TABLE apps ADD COLUMN max_presence_members INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE apps ADD COLUMN max_presence_user_info_size INTEGER NOT NULL DEFAULT 1024;
@@ -0,0 +1 @@
+ALTER TABLE apps ADD COLUMN encryption_master_key TEXT;
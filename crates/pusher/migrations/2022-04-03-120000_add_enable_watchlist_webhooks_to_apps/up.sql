@@ -0,0 +1 @@
+ALTER TABLE apps ADD COLUMN enable_watchlist_webhooks BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE apps ADD COLUMN enable_subscription_count BOOLEAN NOT NULL DEFAULT 0;
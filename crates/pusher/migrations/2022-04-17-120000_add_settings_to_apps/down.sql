@@ -0,0 +1,5 @@
+ALTER TABLE apps DROP COLUMN allowed_origins;
+ALTER TABLE apps DROP COLUMN webhook_events;
+ALTER TABLE apps DROP COLUMN max_message_size;
+ALTER TABLE apps DROP COLUMN max_connections;
+ALTER TABLE apps DROP COLUMN enabled;
@@ -0,0 +1,5 @@
+ALTER TABLE apps ADD COLUMN enabled BOOLEAN NOT NULL DEFAULT 1;
+ALTER TABLE apps ADD COLUMN max_connections INTEGER;
+ALTER TABLE apps ADD COLUMN max_message_size INTEGER NOT NULL DEFAULT 10240;
+ALTER TABLE apps ADD COLUMN webhook_events TEXT NOT NULL DEFAULT '';
+ALTER TABLE apps ADD COLUMN allowed_origins TEXT NOT NULL DEFAULT '';
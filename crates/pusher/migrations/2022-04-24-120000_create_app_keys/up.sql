@@ -0,0 +1,10 @@
+CREATE TABLE app_keys
+(
+    id         INTEGER PRIMARY KEY NOT NULL,
+    app_id     bigint NOT NULL,
+    `key`      TEXT NOT NULL UNIQUE,
+    secret     TEXT NOT NULL,
+    expires_at bigint
+);
+
+CREATE INDEX app_keys_app_id ON app_keys (app_id);
@@ -0,0 +1,183 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpMessage, HttpRequest};
+
+use crate::api::errors::ApiError;
+use crate::config::{AdminScope, AdminToken, Config};
+
+/// Requires an admin token with at least the `read` scope. Use on management
+/// routes with `wrap = "ReadAccess"`.
+pub struct ReadAccess;
+
+/// Requires an admin token with the `manage` scope.
+pub struct ManageAccess;
+
+impl<S> Transform<S> for ReadAccess
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AdminAuth<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AdminAuth {
+            service,
+            scope: AdminScope::Read,
+        }))
+    }
+}
+
+impl<S> Transform<S> for ManageAccess
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AdminAuth<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AdminAuth {
+            service,
+            scope: AdminScope::Manage,
+        }))
+    }
+}
+
+/// Checks the `Authorization: Bearer` header against the configured admin
+/// tokens and makes the granted [`AdminScope`] available to the handler.
+pub struct AdminAuth<S> {
+    service: S,
+    scope: AdminScope,
+}
+
+impl<S> Service for AdminAuth<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let authorization = req
+            .headers()
+            .get("authorization")
+            .and_then(|header| header.to_str().ok());
+
+        let config = req.app_data::<web::Data<Config>>();
+
+        let tokens = config
+            .as_ref()
+            .map(|config| config.admin_tokens.as_slice())
+            .unwrap_or_default();
+
+        match authorize(authorization, tokens, self.scope) {
+            Ok(granted) => {
+                req.extensions_mut().insert(granted);
+
+                Box::pin(self.service.call(req))
+            }
+            Err(e) => Box::pin(ready(Ok(req.error_response(e)))),
+        }
+    }
+}
+
+/// The scope of the bearer token in `authorization`, if it grants at least
+/// `required`.
+fn authorize(
+    authorization: Option<&str>,
+    tokens: &[AdminToken],
+    required: AdminScope,
+) -> Result<AdminScope, ApiError> {
+    let presented = authorization
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or_else(|| ApiError::Unauthorized("missing admin token".to_string()))?;
+
+    let token = tokens
+        .iter()
+        .find(|token| constant_time_eq(token.token.as_bytes(), presented.as_bytes()))
+        .ok_or_else(|| ApiError::Unauthorized("invalid admin token".to_string()))?;
+
+    if token.scope < required {
+        return Err(ApiError::Forbidden(
+            "admin token does not have the manage scope".to_string(),
+        ));
+    }
+
+    Ok(token.scope)
+}
+
+/// The scope granted to the current request by [`ReadAccess`] or [`ManageAccess`].
+pub fn granted_scope(req: &HttpRequest) -> Option<AdminScope> {
+    req.extensions().get::<AdminScope>().copied()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorizes_admin_tokens_by_scope() {
+        let tokens = [
+            AdminToken {
+                token: "reader".to_string(),
+                scope: AdminScope::Read,
+            },
+            AdminToken {
+                token: "manager".to_string(),
+                scope: AdminScope::Manage,
+            },
+        ];
+
+        let authorize = |authorization, required| authorize(authorization, &tokens, required);
+
+        let manage = Some("Bearer manager");
+        let read = Some("Bearer reader");
+
+        assert_eq!(
+            AdminScope::Manage,
+            authorize(manage, AdminScope::Manage).unwrap()
+        );
+        assert_eq!(
+            AdminScope::Manage,
+            authorize(manage, AdminScope::Read).unwrap()
+        );
+        assert_eq!(AdminScope::Read, authorize(read, AdminScope::Read).unwrap());
+
+        assert!(matches!(
+            authorize(read, AdminScope::Manage),
+            Err(ApiError::Forbidden(_))
+        ));
+        assert!(matches!(
+            authorize(Some("Bearer nope"), AdminScope::Read),
+            Err(ApiError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            authorize(Some("reader"), AdminScope::Read),
+            Err(ApiError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            authorize(None, AdminScope::Read),
+            Err(ApiError::Unauthorized(_))
+        ));
+    }
+}
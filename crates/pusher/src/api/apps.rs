@@ -1,37 +1,560 @@
-use std::sync::{Arc};
+use std::sync::Arc;
 
-use actix_web::{get, post, web, HttpRequest, Responder};
+use actix::Addr;
+use actix_web::{delete, get, patch, post, web, HttpRequest};
 use parking_lot::Mutex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{AppRepo, HttpResponse, PusherApp};
+use crate::api::admin::{granted_scope, ManageAccess, ReadAccess};
+use crate::api::errors::ApiError;
+use crate::app::AppKey;
+use crate::auth::now;
+use crate::config::AdminScope;
+use crate::encryption::decode_master_key;
+use crate::repository::WebhookRepo;
+use crate::webhooks::{DeadLetter, WEBHOOK_EVENT_NAMES};
+use crate::ws::errors::ErrorKind;
+use crate::ws::TerminateAppConnections;
+use crate::{AppRepo, HttpResponse, PusherApp, WebSocketHandler};
+
+/// How long a replaced key pair keeps working by default, in seconds.
+const DEFAULT_RETIRED_KEY_TTL: i64 = 24 * 60 * 60;
+
+#[derive(Deserialize)]
+pub struct SecretsQuery {
+    #[serde(default)]
+    include_secrets: bool,
+}
+
+impl SecretsQuery {
+    /// Whether secrets are shown, which has to be asked for with a manage token.
+    fn granted(&self, req: &HttpRequest) -> Result<bool, ApiError> {
+        if self.include_secrets && granted_scope(req) != Some(AdminScope::Manage) {
+            return Err(ApiError::Forbidden(
+                "include_secrets requires the manage scope".to_string(),
+            ));
+        }
+
+        Ok(self.include_secrets)
+    }
+}
+
+/// The app without its secret, encryption master key and the secrets of its
+/// additional keys.
+fn redacted(app: &PusherApp) -> serde_json::Value {
+    let mut value = serde_json::to_value(app).unwrap();
+
+    if let Some(app) = value.as_object_mut() {
+        app.remove("secret");
+        app.remove("encryption_master_key");
+
+        if let Some(keys) = app.get_mut("keys").and_then(|keys| keys.as_array_mut()) {
+            for key in keys.iter_mut().filter_map(|key| key.as_object_mut()) {
+                key.remove("secret");
+            }
+        }
+    }
+
+    value
+}
+
+#[get("/apps", wrap = "ReadAccess")]
+pub async fn all(
+    req: HttpRequest,
+    query: web::Query<SecretsQuery>,
+    repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
+) -> Result<HttpResponse, ApiError> {
+    let include_secrets = query.granted(&req)?;
 
-#[get("/apps")]
-pub async fn all(_req: HttpRequest, repo: web::Data<Arc<Mutex<dyn AppRepo>>>) -> impl Responder {
     let apps = repo.lock().all();
 
-    HttpResponse::Ok().json(apps)
+    if include_secrets {
+        Ok(HttpResponse::Ok().json(apps))
+    } else {
+        Ok(HttpResponse::Ok().json(apps.iter().map(redacted).collect::<Vec<_>>()))
+    }
+}
+
+/// Per-app settings accepted when creating or updating an app, fields left
+/// out keep their current value.
+#[derive(Debug, Default, Deserialize)]
+pub struct AppSettings {
+    enabled: Option<bool>,
+    enable_client_events: Option<bool>,
+    #[serde(default, with = "serde_with::rust::double_option")]
+    max_connections: Option<Option<i32>>,
+    max_message_size: Option<i32>,
+    #[serde(default, with = "serde_with::rust::double_option")]
+    webhook_url: Option<Option<String>>,
+    webhook_events: Option<Vec<String>>,
+    enable_client_event_webhooks: Option<bool>,
+    enable_watchlist_webhooks: Option<bool>,
+    enable_subscription_count: Option<bool>,
+    allowed_origins: Option<Vec<String>>,
+    max_presence_members: Option<i32>,
+    max_presence_user_info_size: Option<i32>,
+    #[serde(default, with = "serde_with::rust::double_option")]
+    encryption_master_key: Option<Option<String>>,
+}
+
+impl AppSettings {
+    fn apply(&self, app: &mut PusherApp) -> Result<(), ApiError> {
+        if let Some(Some(max_connections)) = self.max_connections {
+            if max_connections < 0 {
+                return Err(ApiError::BadRequest(
+                    "max_connections must not be negative".to_string(),
+                ));
+            }
+        }
+
+        if let Some(max_message_size) = self.max_message_size {
+            if max_message_size <= 0 {
+                return Err(ApiError::BadRequest(
+                    "max_message_size must be positive".to_string(),
+                ));
+            }
+        }
+
+        if let Some(max_presence_members) = self.max_presence_members {
+            if max_presence_members <= 0 {
+                return Err(ApiError::BadRequest(
+                    "max_presence_members must be positive".to_string(),
+                ));
+            }
+        }
+
+        if let Some(max_presence_user_info_size) = self.max_presence_user_info_size {
+            if max_presence_user_info_size <= 0 {
+                return Err(ApiError::BadRequest(
+                    "max_presence_user_info_size must be positive".to_string(),
+                ));
+            }
+        }
+
+        if let Some(webhook_events) = &self.webhook_events {
+            if let Some(name) = webhook_events
+                .iter()
+                .find(|name| !WEBHOOK_EVENT_NAMES.contains(&name.as_str()))
+            {
+                return Err(ApiError::BadRequest(format!(
+                    "unknown webhook event: {}",
+                    name
+                )));
+            }
+        }
+
+        if let Some(allowed_origins) = &self.allowed_origins {
+            if allowed_origins.iter().any(|origin| origin.contains(',')) {
+                return Err(ApiError::BadRequest(
+                    "allowed_origins must not contain commas".to_string(),
+                ));
+            }
+        }
+
+        if let Some(Some(master_key)) = &self.encryption_master_key {
+            decode_master_key(master_key).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        }
+
+        if let Some(enabled) = self.enabled {
+            app.enabled = enabled;
+        }
+
+        if let Some(enable_client_events) = self.enable_client_events {
+            app.enable_client_events = enable_client_events;
+        }
+
+        if let Some(max_connections) = self.max_connections {
+            app.max_connections = max_connections;
+        }
+
+        if let Some(max_message_size) = self.max_message_size {
+            app.max_message_size = max_message_size;
+        }
+
+        if let Some(webhook_url) = &self.webhook_url {
+            app.webhook_url = webhook_url.clone();
+        }
+
+        if let Some(webhook_events) = &self.webhook_events {
+            app.webhook_events = webhook_events.clone();
+        }
+
+        if let Some(enable_client_event_webhooks) = self.enable_client_event_webhooks {
+            app.enable_client_event_webhooks = enable_client_event_webhooks;
+        }
+
+        if let Some(enable_watchlist_webhooks) = self.enable_watchlist_webhooks {
+            app.enable_watchlist_webhooks = enable_watchlist_webhooks;
+        }
+
+        if let Some(enable_subscription_count) = self.enable_subscription_count {
+            app.enable_subscription_count = enable_subscription_count;
+        }
+
+        if let Some(allowed_origins) = &self.allowed_origins {
+            app.allowed_origins = allowed_origins.clone();
+        }
+
+        if let Some(max_presence_members) = self.max_presence_members {
+            app.max_presence_members = max_presence_members;
+        }
+
+        if let Some(max_presence_user_info_size) = self.max_presence_user_info_size {
+            app.max_presence_user_info_size = max_presence_user_info_size;
+        }
+
+        if let Some(encryption_master_key) = &self.encryption_master_key {
+            app.encryption_master_key = encryption_master_key.clone();
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Deserialize)]
 pub struct CreateAppPayload {
     name: String,
+    #[serde(flatten)]
+    settings: AppSettings,
 }
 
-#[post("/apps")]
+#[post("/apps", wrap = "ManageAccess")]
 pub async fn create(
-    _req: HttpRequest,
     body: web::Json<CreateAppPayload>,
     repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
-) -> impl Responder {
-    let app = PusherApp::new(body.name.clone());
+) -> Result<HttpResponse, ApiError> {
+    let mut app = PusherApp::new(body.name.clone());
 
-    repo.lock().insert_app(&app).unwrap();
+    body.settings.apply(&mut app)?;
 
-    HttpResponse::Created().json(app)
+    repo.lock().insert_app(&app)?;
+
+    Ok(HttpResponse::Created().json(app))
 }
 
 #[derive(Deserialize)]
 pub struct AppQuery {
     app_id: i64,
 }
+
+#[get("/apps/{app_id}", wrap = "ReadAccess")]
+pub async fn show(
+    req: HttpRequest,
+    path: web::Path<AppQuery>,
+    query: web::Query<SecretsQuery>,
+    repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
+) -> Result<HttpResponse, ApiError> {
+    let include_secrets = query.granted(&req)?;
+
+    let app = repo
+        .lock()
+        .find_by_id(path.app_id)
+        .ok_or(ApiError::AppNotFound)?;
+
+    if include_secrets {
+        Ok(HttpResponse::Ok().json(app))
+    } else {
+        Ok(HttpResponse::Ok().json(redacted(&app)))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateAppPayload {
+    name: Option<String>,
+    #[serde(flatten)]
+    settings: AppSettings,
+}
+
+/// Updates the app's name and settings. Disabling an app closes its open
+/// connections with a 4003 `pusher:error`.
+#[patch("/apps/{app_id}", wrap = "ManageAccess")]
+pub async fn update(
+    path: web::Path<AppQuery>,
+    body: web::Json<UpdateAppPayload>,
+    repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
+    handler: web::Data<Addr<WebSocketHandler>>,
+) -> Result<HttpResponse, ApiError> {
+    let mut repo = repo.lock();
+
+    let mut app = repo.find_by_id(path.app_id).ok_or(ApiError::AppNotFound)?;
+
+    let was_enabled = app.enabled;
+
+    if let Some(name) = &body.name {
+        app.name = name.clone();
+    }
+
+    body.settings.apply(&mut app)?;
+
+    repo.update_app(&app)?;
+
+    if was_enabled && !app.enabled {
+        handler.do_send(TerminateAppConnections {
+            app_id: app.id,
+            error: ErrorKind::AppDisabled,
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(app))
+}
+
+/// Deletes the app and closes its open connections with a 4001 `pusher:error`.
+#[delete("/apps/{app_id}", wrap = "ManageAccess")]
+pub async fn delete(
+    path: web::Path<AppQuery>,
+    repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
+    handler: web::Data<Addr<WebSocketHandler>>,
+) -> Result<HttpResponse, ApiError> {
+    let mut repo = repo.lock();
+
+    if repo.find_by_id(path.app_id).is_none() {
+        return Err(ApiError::AppNotFound);
+    }
+
+    repo.delete_app(path.app_id)?;
+
+    handler.do_send(TerminateAppConnections {
+        app_id: path.app_id,
+        error: ErrorKind::AppNotFound,
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct RetireQuery {
+    /// Seconds the retired key pair is still accepted for.
+    expires_in: Option<i64>,
+}
+
+impl RetireQuery {
+    fn expires_at(&self) -> Result<i64, ApiError> {
+        match self.expires_in.unwrap_or(DEFAULT_RETIRED_KEY_TTL) {
+            expires_in if expires_in < 0 => Err(ApiError::BadRequest(
+                "expires_in must not be negative".to_string(),
+            )),
+            expires_in => Ok(now() + expires_in),
+        }
+    }
+}
+
+/// Gives the app a new key and secret and returns it. The old pair keeps
+/// working for `expires_in` seconds so clients and servers can move over.
+#[post("/apps/{app_id}/secret", wrap = "ManageAccess")]
+pub async fn rotate_secret(
+    path: web::Path<AppQuery>,
+    query: web::Query<RetireQuery>,
+    repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
+) -> Result<HttpResponse, ApiError> {
+    let expires_at = query.expires_at()?;
+
+    let mut repo = repo.lock();
+
+    let mut app = repo.find_by_id(path.app_id).ok_or(ApiError::AppNotFound)?;
+
+    let retired = app.rotate_key(expires_at);
+
+    repo.rotate_key(&app, &retired)?;
+
+    Ok(HttpResponse::Ok().json(app))
+}
+
+/// Adds an active key pair to the app.
+#[post("/apps/{app_id}/keys", wrap = "ManageAccess")]
+pub async fn add_key(
+    path: web::Path<AppQuery>,
+    repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
+) -> Result<HttpResponse, ApiError> {
+    let mut repo = repo.lock();
+
+    if repo.find_by_id(path.app_id).is_none() {
+        return Err(ApiError::AppNotFound);
+    }
+
+    let key = AppKey::generate();
+
+    repo.add_key(path.app_id, &key)?;
+
+    Ok(HttpResponse::Created().json(key))
+}
+
+#[derive(Deserialize)]
+pub struct KeyPath {
+    app_id: i64,
+    key: String,
+}
+
+/// Deprecates one of the app's additional key pairs, which stops being
+/// accepted after `expires_in` seconds. The app's own key is replaced by
+/// rotating the secret instead.
+#[post("/apps/{app_id}/keys/{key}/retire", wrap = "ManageAccess")]
+pub async fn retire_key(
+    path: web::Path<KeyPath>,
+    query: web::Query<RetireQuery>,
+    repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
+) -> Result<HttpResponse, ApiError> {
+    let expires_at = query.expires_at()?;
+
+    let mut repo = repo.lock();
+
+    let app = repo.find_by_id(path.app_id).ok_or(ApiError::AppNotFound)?;
+
+    if app.key == path.key {
+        return Err(ApiError::BadRequest(
+            "the app's own key is replaced by rotating its secret".to_string(),
+        ));
+    }
+
+    let mut key = app
+        .keys
+        .into_iter()
+        .find(|key| key.key == path.key)
+        .ok_or(ApiError::KeyNotFound)?;
+
+    // retiring twice never extends a key's lifetime
+    let expires_at = key.expires_at.map_or(expires_at, |e| e.min(expires_at));
+
+    repo.retire_key(path.app_id, &key.key, expires_at)?;
+
+    key.expires_at = Some(expires_at);
+
+    Ok(HttpResponse::Ok().json(key))
+}
+
+#[derive(Serialize)]
+pub struct DeadLetters {
+    dead_letters: Vec<DeadLetter>,
+}
+
+#[get("/apps/{app_id}/webhooks/dead_letters", wrap = "ReadAccess")]
+pub async fn dead_letters(
+    path: web::Path<AppQuery>,
+    repo: web::Data<Arc<Mutex<dyn AppRepo>>>,
+    outbox: web::Data<Arc<Mutex<dyn WebhookRepo>>>,
+) -> Result<HttpResponse, ApiError> {
+    if repo.lock().find_by_id(path.app_id).is_none() {
+        return Err(ApiError::AppNotFound);
+    }
+
+    Ok(HttpResponse::Ok().json(DeadLetters {
+        dead_letters: outbox.lock().dead_letters(path.app_id),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Take, TestServer, ADMIN_TOKEN};
+    use crate::ws::Disconnect;
+    use actix_web::rt::System;
+    use actix_web::test::{self, TestRequest};
+
+    #[test]
+    fn applies_only_given_settings() {
+        let mut app = PusherApp::new("test".to_string());
+        app.webhook_url = Some("http://localhost/webhooks".to_string());
+        app.max_connections = Some(10);
+
+        let payload: UpdateAppPayload = serde_json::from_str(
+            r#"{"webhook_url":null,"allowed_origins":["https://example.com"]}"#,
+        )
+        .unwrap();
+
+        payload.settings.apply(&mut app).unwrap();
+
+        assert_eq!(None, app.webhook_url);
+        assert_eq!(Some(10), app.max_connections);
+        assert_eq!(vec!["https://example.com".to_string()], app.allowed_origins);
+
+        let payload: UpdateAppPayload =
+            serde_json::from_str(r#"{"webhook_events":["channel_occupied","nope"]}"#).unwrap();
+
+        assert!(payload.settings.apply(&mut app).is_err());
+
+        let payload: UpdateAppPayload =
+            serde_json::from_str(r#"{"max_presence_members":0}"#).unwrap();
+
+        assert!(payload.settings.apply(&mut app).is_err());
+    }
+
+    #[test]
+    fn redacts_secrets() {
+        let mut app = PusherApp::new("test".to_string());
+        app.encryption_master_key = Some("key".to_string());
+        app.keys.push(AppKey::generate());
+
+        let value = redacted(&app);
+
+        assert_eq!(app.key, value["key"]);
+        assert!(value.get("secret").is_none());
+        assert!(value.get("encryption_master_key").is_none());
+        assert_eq!(app.keys[0].key, value["keys"][0]["key"]);
+        assert!(value["keys"][0].get("secret").is_none());
+    }
+
+    /// Runs `request` against the app's admin endpoints and returns what its
+    /// connected client received by the time the handler processed it.
+    async fn received_after(app: &PusherApp, request: TestRequest) -> Vec<serde_json::Value> {
+        let server = TestServer::start(&[app]);
+
+        let (ws, client) = server.connect(app).await;
+
+        let mut service = test::init_service(
+            actix_web::App::new()
+                .configure(server.data())
+                .service(update)
+                .service(delete),
+        )
+        .await;
+
+        let request = request
+            .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+            .to_request();
+
+        assert!(test::call_service(&mut service, request)
+            .await
+            .status()
+            .is_success());
+
+        server
+            .handler
+            .send(Disconnect {
+                id: ws.id,
+                app_id: app.id,
+            })
+            .await
+            .unwrap();
+
+        client.send(Take).await.unwrap()
+    }
+
+    fn closed_with(received: &[serde_json::Value], code: i32) -> bool {
+        received
+            .iter()
+            .any(|message| message["event"] == "pusher:error" && message["data"]["code"] == code)
+    }
+
+    #[test]
+    fn disabling_an_app_closes_its_connections() {
+        System::new("apps").block_on(async {
+            let app = PusherApp::new("apps".to_string());
+
+            let request = TestRequest::patch()
+                .uri(&format!("/apps/{}", app.id))
+                .set_json(&serde_json::json!({"enabled": false}));
+
+            assert!(closed_with(&received_after(&app, request).await, 4003));
+        });
+    }
+
+    #[test]
+    fn deleting_an_app_closes_its_connections() {
+        System::new("apps").block_on(async {
+            let app = PusherApp::new("apps".to_string());
+
+            let request = TestRequest::delete().uri(&format!("/apps/{}", app.id));
+
+            assert!(closed_with(&received_after(&app, request).await, 4001));
+        });
+    }
+}
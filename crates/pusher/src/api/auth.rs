@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+
+use crate::api::errors::ApiError;
+use crate::app::App;
+use crate::auth::{now, validate_request, RequestPayload};
+use crate::config::Config;
+use crate::AppRepo;
+
+/// Extracts the app from the `{app_id}` path segment and verifies the request
+/// was signed with its secret, rejecting requests to disabled apps. Any handler under `/apps/{app_id}/` that speaks
+/// the Pusher HTTP API should take this instead of reading the body directly.
+pub struct SignedRequest {
+    pub app: App,
+    pub body: web::Bytes,
+}
+
+impl SignedRequest {
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ApiError> {
+        serde_json::from_slice(&self.body).map_err(|e| ApiError::BadRequest(e.to_string()))
+    }
+}
+
+impl FromRequest for SignedRequest {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let body = web::Bytes::from_request(&req, payload);
+
+        Box::pin(async move {
+            let body = body
+                .await
+                .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+            let app = find_app(&req)?;
+
+            let params = web::Query::<BTreeMap<String, String>>::from_query(req.query_string())
+                .map_err(|e| ApiError::BadRequest(e.to_string()))?
+                .into_inner();
+
+            let grace = req
+                .app_data::<web::Data<Config>>()
+                .map(|config| config.auth_timestamp_grace)
+                .unwrap_or_else(|| Config::default().auth_timestamp_grace);
+
+            let payload = RequestPayload {
+                method: req.method().as_str(),
+                path: req.path(),
+                params: &params,
+                body: &body,
+            };
+
+            validate_request(&app, &payload, now() as u64, grace)
+                .map_err(|e| ApiError::Unauthorized(e.0.to_string()))?;
+
+            if !app.enabled {
+                return Err(ApiError::Forbidden("app is disabled".to_string()));
+            }
+
+            Ok(SignedRequest { app, body })
+        })
+    }
+}
+
+fn find_app(req: &HttpRequest) -> Result<App, ApiError> {
+    let app_id = req
+        .match_info()
+        .get("app_id")
+        .and_then(|id| id.parse::<i64>().ok())
+        .ok_or(ApiError::AppNotFound)?;
+
+    req.app_data::<web::Data<Arc<Mutex<dyn AppRepo>>>>()
+        .and_then(|repo| repo.lock().find_by_id(app_id))
+        .ok_or(ApiError::AppNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{signed, TestServer};
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::{get, rt::System, test, HttpResponse};
+
+    #[get("/apps/{app_id}/signed")]
+    async fn signed_only(request: SignedRequest) -> HttpResponse {
+        HttpResponse::Ok().json(serde_json::json!({ "app": request.app.name }))
+    }
+
+    #[test]
+    fn rejects_requests_to_disabled_apps() {
+        System::new("auth").block_on(async {
+            let enabled = App::new("enabled".to_string());
+            let disabled = App {
+                enabled: false,
+                ..App::new("disabled".to_string())
+            };
+            let server = TestServer::start(&[&enabled, &disabled]);
+
+            let mut service = test::init_service(
+                actix_web::App::new()
+                    .configure(server.data())
+                    .service(signed_only),
+            )
+            .await;
+
+            let status = |app: &App| {
+                let path = format!("/apps/{}/signed", app.id);
+                signed(app, Method::GET, &path, &[], "").to_request()
+            };
+
+            let response = test::call_service(&mut service, status(&enabled)).await;
+            assert_eq!(StatusCode::OK, response.status());
+
+            let response = test::call_service(&mut service, status(&disabled)).await;
+            assert_eq!(StatusCode::FORBIDDEN, response.status());
+        });
+    }
+}
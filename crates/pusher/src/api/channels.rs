@@ -1,32 +1,36 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Arc;
 
+use actix_web::{get, web};
 
-use actix_web::{get, web, Responder};
+use crate::HttpResponse;
 
-
-
-use crate::{HttpResponse};
-
-
-use serde::{Deserialize, Serialize};
 use crate::adapter::Adapter;
+use crate::api::auth::SignedRequest;
+use crate::api::errors::ApiError;
+use crate::kind::Channel;
+use crate::namespace::Namespace;
+use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Deserialize)]
+pub struct AllQuery {
+    pub filter_by_prefix: Option<String>,
+    pub info: Option<String>,
+}
 
 #[derive(Debug, Deserialize)]
-pub struct AppPath {
-    pub app_id: i64,
+pub struct ChannelPath {
+    pub channel_name: String,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct AllQuery {
-    pub filter_by_prefix: Option<String>,
+pub struct ChannelQuery {
     pub info: Option<String>,
 }
 
 #[derive(Serialize, Default)]
 pub struct Channels {
-    pub channels: HashMap<String, ChannelResult>
+    pub channels: HashMap<String, ChannelResult>,
 }
 
 #[derive(Serialize)]
@@ -37,33 +41,272 @@ pub struct ChannelResult {
     pub subscription_count: Option<usize>,
 }
 
+#[derive(Serialize)]
+pub struct Users {
+    pub users: Vec<User>,
+}
+
+#[derive(Serialize)]
+pub struct User {
+    pub id: String,
+}
+
+#[derive(Serialize)]
+pub struct ChannelInfo {
+    pub occupied: bool,
+    #[serde(flatten)]
+    pub attributes: ChannelResult,
+}
+
+/// Whether `attribute` is listed in a comma separated `info` parameter.
+pub fn info_requested(info: Option<&str>, attribute: &str) -> bool {
+    info.map(|info| info.split(',').any(|a| a.trim() == attribute))
+        .unwrap_or(false)
+}
+
+/// Collects the attributes requested by `info` for a single channel.
+pub fn channel_attributes<R: Clone>(
+    ns: &Namespace<R>,
+    channel: &Channel,
+    info: Option<&str>,
+) -> Result<ChannelResult, ApiError> {
+    let user_count = if info_requested(info, "user_count") {
+        if !matches!(channel, Channel::Presence(_)) {
+            return Err(ApiError::BadRequest(
+                "user_count may only be requested for presence channels".to_string(),
+            ));
+        }
+
+        Some(ns.users_per_channel(channel))
+    } else {
+        None
+    };
+
+    let subscription_count = if info_requested(info, "subscription_count") {
+        Some(ns.member_count_by_channel(channel))
+    } else {
+        None
+    };
+
+    Ok(ChannelResult {
+        user_count,
+        subscription_count,
+    })
+}
 
 #[get("/apps/{app_id}/channels")]
 pub async fn all(
-    path: web::Path<AppPath>,
+    request: SignedRequest,
     query: web::Query<AllQuery>,
     adapter: web::Data<Arc<dyn Adapter>>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
+    let info = query.info.as_deref();
+
+    let prefix = query.filter_by_prefix.as_deref().unwrap_or_default();
+
+    if info_requested(info, "user_count") && !prefix.starts_with("presence-") {
+        return Err(ApiError::BadRequest(
+            "user_count may only be requested when filtering by the presence- prefix".to_string(),
+        ));
+    }
+
     let mut response_payload = Channels::default();
 
-    let ns = adapter.namespace(path.app_id);
+    let ns = adapter.namespace(request.app.id);
 
-    let channels = ns.channels();
+    for channel in ns.channels() {
+        let name = channel.to_string();
 
-    let with_user_count = match &query.info {
-        Some(v) => v.contains(&"user_count".to_string()),
-        _ => false,
-    };
+        if !name.starts_with(prefix) {
+            continue;
+        }
+
+        let attributes = channel_attributes(&*ns, &channel, info)?;
+
+        response_payload.channels.insert(name, attributes);
+    }
+
+    Ok(HttpResponse::Ok().json(response_payload))
+}
+
+#[get("/apps/{app_id}/channels/{channel_name}")]
+pub async fn show(
+    request: SignedRequest,
+    path: web::Path<ChannelPath>,
+    query: web::Query<ChannelQuery>,
+    adapter: web::Data<Arc<dyn Adapter>>,
+) -> Result<HttpResponse, ApiError> {
+    let ns = adapter.namespace(request.app.id);
+
+    let channel = Channel::from(path.channel_name.clone());
+
+    let attributes = channel_attributes(&*ns, &channel, query.info.as_deref())?;
+
+    Ok(HttpResponse::Ok().json(ChannelInfo {
+        occupied: ns.member_count_by_channel(&channel) > 0,
+        attributes,
+    }))
+}
 
-    for channel in channels {
-        response_payload.channels.insert(channel.to_string(), ChannelResult {
-            user_count: match with_user_count {
-                true => Some(ns.users_per_channel(&channel)),
-                _ => None,
-            },
-            subscription_count: None
+#[get("/apps/{app_id}/channels/{channel_name}/users")]
+pub async fn users(
+    request: SignedRequest,
+    path: web::Path<ChannelPath>,
+    adapter: web::Data<Arc<dyn Adapter>>,
+) -> Result<HttpResponse, ApiError> {
+    let channel = Channel::from(path.channel_name.clone());
+
+    if !matches!(channel, Channel::Presence(_)) {
+        return Err(ApiError::BadRequest(
+            "users may only be requested for presence channels".to_string(),
+        ));
+    }
+
+    let user_ids: BTreeSet<String> = adapter
+        .namespace(request.app.id)
+        .channel_members(&channel)
+        .into_iter()
+        .map(|member| member.user_id)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Users {
+        users: user_ids.into_iter().map(|id| User { id }).collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use crate::messages::PusherMessageChannelData;
+    use crate::testing::{signed, TestServer};
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::{rt::System, test};
+    use serde_json::{json, Value};
+
+    fn member(user_id: &str) -> Option<PusherMessageChannelData> {
+        Some(PusherMessageChannelData {
+            user_id: user_id.to_string(),
+            user_info: json!({}),
+        })
+    }
+
+    /// Subscribes two sockets of user 1 and one of user 2 to `presence-room`,
+    /// and one socket to `lobby`.
+    fn populate(server: &TestServer, app: &App) {
+        let ns = server.adapter.namespace(app.id);
+        let room = Channel::from("presence-room".to_string());
+
+        ns.add_to_channel(1, &room, member("1"));
+        ns.add_to_channel(2, &room, member("1"));
+        ns.add_to_channel(3, &room, member("2"));
+        ns.add_to_channel(4, &Channel::from("lobby".to_string()), None);
+    }
+
+    #[test]
+    fn shows_a_single_channel() {
+        System::new("channels").block_on(async {
+            let app = App::new("channels".to_string());
+            let server = TestServer::start(&[&app]);
+
+            populate(&server, &app);
+
+            let mut service =
+                test::init_service(actix_web::App::new().configure(server.data()).service(show))
+                    .await;
+
+            let path = format!("/apps/{}/channels/presence-room", app.id);
+            let info = [("info", "user_count,subscription_count")];
+            let request = signed(&app, Method::GET, &path, &info, "").to_request();
+
+            let response: Value = test::read_response_json(&mut service, request).await;
+
+            assert_eq!(
+                json!({"occupied": true, "user_count": 2, "subscription_count": 3}),
+                response
+            );
+
+            let path = format!("/apps/{}/channels/empty", app.id);
+            let request = signed(&app, Method::GET, &path, &[], "").to_request();
+
+            let response: Value = test::read_response_json(&mut service, request).await;
+
+            assert_eq!(json!({"occupied": false}), response);
         });
     }
 
-    HttpResponse::Ok().json(response_payload)
+    #[test]
+    fn lists_presence_users_once() {
+        System::new("channels").block_on(async {
+            let app = App::new("channels".to_string());
+            let server = TestServer::start(&[&app]);
+
+            populate(&server, &app);
+
+            let mut service = test::init_service(
+                actix_web::App::new()
+                    .configure(server.data())
+                    .service(users),
+            )
+            .await;
+
+            let path = format!("/apps/{}/channels/presence-room/users", app.id);
+            let request = signed(&app, Method::GET, &path, &[], "").to_request();
+
+            let response: Value = test::read_response_json(&mut service, request).await;
+
+            assert_eq!(json!({"users": [{"id": "1"}, {"id": "2"}]}), response);
+
+            let path = format!("/apps/{}/channels/lobby/users", app.id);
+            let request = signed(&app, Method::GET, &path, &[], "").to_request();
+
+            let response = test::call_service(&mut service, request).await;
+
+            assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        });
+    }
+
+    #[test]
+    fn filters_channels_by_prefix() {
+        System::new("channels").block_on(async {
+            let app = App::new("channels".to_string());
+            let server = TestServer::start(&[&app]);
+
+            populate(&server, &app);
+
+            let mut service =
+                test::init_service(actix_web::App::new().configure(server.data()).service(all))
+                    .await;
+
+            let path = format!("/apps/{}/channels", app.id);
+            let query = [
+                ("filter_by_prefix", "presence-"),
+                ("info", "subscription_count,user_count"),
+            ];
+            let request = signed(&app, Method::GET, &path, &query, "").to_request();
+
+            let response: Value = test::read_response_json(&mut service, request).await;
+
+            assert_eq!(
+                json!({"channels": {"presence-room": {"user_count": 2, "subscription_count": 3}}}),
+                response
+            );
+
+            let request = signed(&app, Method::GET, &path, &[], "").to_request();
+
+            let response: Value = test::read_response_json(&mut service, request).await;
+
+            assert_eq!(
+                json!({"channels": {"presence-room": {}, "lobby": {}}}),
+                response
+            );
+
+            let query = [("info", "user_count")];
+            let request = signed(&app, Method::GET, &path, &query, "").to_request();
+
+            let response = test::call_service(&mut service, request).await;
+
+            assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        });
+    }
 }
@@ -1 +1,58 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::error;
+use serde_json::json;
+use std::fmt::{Display, Formatter};
 
+/// Errors returned by the HTTP API as `{"error": "..."}` with a matching status code.
+#[derive(Debug)]
+pub enum ApiError {
+    AppNotFound,
+    KeyNotFound,
+    Unauthorized(String),
+    Forbidden(String),
+    BadRequest(String),
+    /// Storage or another dependency failed, the details are only logged.
+    Internal(String),
+}
+
+impl From<Box<dyn std::error::Error>> for ApiError {
+    fn from(error: Box<dyn std::error::Error>) -> Self {
+        ApiError::Internal(error.to_string())
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::AppNotFound => write!(f, "app not found"),
+            ApiError::KeyNotFound => write!(f, "key not found"),
+            ApiError::Unauthorized(reason) => write!(f, "unauthorized: {}", reason),
+            ApiError::Forbidden(reason) => write!(f, "forbidden: {}", reason),
+            ApiError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            ApiError::Internal(_) => write!(f, "internal error"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::AppNotFound | ApiError::KeyNotFound => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Internal(reason) = self {
+            error!("api request failed: {}", reason);
+        }
+
+        HttpResponse::build(self.status_code()).json(json!({
+            "error": self.to_string(),
+        }))
+    }
+}
@@ -1,39 +1,154 @@
+use std::convert::TryFrom;
 use std::sync::Arc;
 
 use actix::Addr;
-use actix_web::{post, web, HttpRequest, Responder};
+use actix_web::{post, web, HttpResponse};
 use serde_json::json;
 
+use crate::adapter::Adapter;
+use crate::api::auth::SignedRequest;
+use crate::api::channels::{channel_attributes, ChannelResult};
+use crate::api::errors::ApiError;
+use crate::app::App;
+use crate::encryption;
+use crate::kind::{Channel, SERVER_TO_USER_PREFIX};
+use crate::socket::Socket;
 use crate::ws::Broadcast;
-use crate::{AppRepo, HttpResponse, WebSocketHandler};
-use parking_lot::Mutex as PMutex;
+use crate::WebSocketHandler;
 
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
-pub struct AppQuery {
-    pub app_id: i64,
-}
+const MAX_EVENT_NAME_LENGTH: usize = 200;
+const MAX_CHANNEL_NAME_LENGTH: usize = 200;
+const MAX_CHANNELS_PER_EVENT: usize = 100;
+const MAX_EVENTS_PER_BATCH: usize = 10;
 
 #[derive(Debug, Deserialize)]
 pub struct Event {
     pub name: String,
-    #[serde(with = "serde_with::json::nested")]
-    pub data: serde_json::Value,
+    pub data: String,
     pub channels: Option<Vec<String>>,
     pub channel: Option<String>,
     pub socket_id: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BatchEvent {
+    pub name: String,
+    pub data: String,
+    pub channel: String,
+    pub socket_id: Option<String>,
+    pub info: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Batch {
+    pub batch: Vec<BatchEvent>,
+}
+
+/// Applies the limits Pusher enforces on triggered events, with the app's
+/// `max_message_size` in place of Pusher's 10KB.
+pub fn validate_event(
+    app: &App,
+    name: &str,
+    channels: &[String],
+    data: &str,
+) -> Result<(), ApiError> {
+    if name.is_empty() || name.len() > MAX_EVENT_NAME_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "event name must be between 1 and {} characters",
+            MAX_EVENT_NAME_LENGTH
+        )));
+    }
+
+    if channels.is_empty() || channels.len() > MAX_CHANNELS_PER_EVENT {
+        return Err(ApiError::BadRequest(format!(
+            "events must be sent to between 1 and {} channels",
+            MAX_CHANNELS_PER_EVENT
+        )));
+    }
+
+    for channel in channels {
+        let name = channel
+            .strip_prefix(SERVER_TO_USER_PREFIX)
+            .unwrap_or(channel);
+
+        let valid = !name.is_empty()
+            && channel.len() <= MAX_CHANNEL_NAME_LENGTH
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_=@,.;".contains(c));
+
+        if !valid {
+            return Err(ApiError::BadRequest(format!(
+                "invalid channel name: {}",
+                channel
+            )));
+        }
+    }
+
+    if data.len() > app.max_message_size as usize {
+        return Err(ApiError::BadRequest(format!(
+            "event data exceeds {} bytes",
+            app.max_message_size
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks that events on `private-encrypted-` channels carry a `{nonce, ciphertext}`
+/// payload, encrypting plaintext data instead when the app has a master key.
+fn encrypted_payload(app: &App, channels: &[String], data: String) -> Result<String, ApiError> {
+    let encrypted = channels
+        .iter()
+        .any(|channel| matches!(Channel::from(channel.clone()), Channel::PrivateEncrypted(_)));
+
+    if !encrypted {
+        return Ok(data);
+    }
+
+    if channels.len() > 1 {
+        return Err(ApiError::BadRequest(
+            "cannot trigger to multiple channels if one is encrypted".to_string(),
+        ));
+    }
+
+    if encryption::is_encrypted(&data) {
+        return Ok(data);
+    }
+
+    match &app.encryption_master_key {
+        Some(master_key) => {
+            let master_key = encryption::decode_master_key(master_key)
+                .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+            let encrypted = encryption::encrypt(&channels[0], &master_key, &data);
+
+            Ok(serde_json::to_string(&encrypted).unwrap())
+        }
+        None => Err(ApiError::BadRequest(
+            "data on encrypted channels must be a {nonce, ciphertext} payload".to_string(),
+        )),
+    }
+}
+
+/// Parses the `socket_id` of the connection to exclude from a broadcast.
+fn excluded_socket(socket_id: &Option<String>) -> Result<Option<usize>, ApiError> {
+    match socket_id {
+        Some(socket_id) => Socket::try_from(socket_id.clone())
+            .map(|socket| Some(socket.id))
+            .map_err(|e| ApiError::BadRequest(e.to_string())),
+        None => Ok(None),
+    }
+}
+
 #[post("/apps/{app_id}/events")]
 pub async fn publish(
-    _req: HttpRequest,
-    query: web::Path<AppQuery>,
-    event: web::Json<Event>,
+    request: SignedRequest,
     handler: web::Data<Addr<WebSocketHandler>>,
-    repo: web::Data<Arc<PMutex<dyn AppRepo>>>,
-) -> impl Responder {
-    let app = repo.lock().find_by_id(query.app_id).unwrap();
+) -> Result<HttpResponse, ApiError> {
+    let event: Event = request.json()?;
 
     let channels = if let Some(channels) = &event.channels {
         channels.clone()
@@ -43,17 +158,156 @@ pub async fn publish(
         vec![]
     };
 
+    validate_event(&request.app, &event.name, &channels, &event.data)?;
+
+    let message = encrypted_payload(&request.app, &channels, event.data)?;
+
+    let except = excluded_socket(&event.socket_id)?;
+
     let broadcast = Broadcast {
         channels,
-        event: event.name.clone(),
-        except: None,
-        message: event.data.clone(),
-        app,
+        event: event.name,
+        except,
+        message,
+        app: request.app,
     };
 
     handler.do_send(broadcast);
 
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok()
         .header("content-type", "application/json")
-        .json(json!({}))
+        .json(json!({})))
+}
+
+#[post("/apps/{app_id}/batch_events")]
+pub async fn batch(
+    request: SignedRequest,
+    handler: web::Data<Addr<WebSocketHandler>>,
+    adapter: web::Data<Arc<dyn Adapter>>,
+) -> Result<HttpResponse, ApiError> {
+    let payload: Batch = request.json()?;
+
+    if payload.batch.is_empty() || payload.batch.len() > MAX_EVENTS_PER_BATCH {
+        return Err(ApiError::BadRequest(format!(
+            "batch must contain between 1 and {} events",
+            MAX_EVENTS_PER_BATCH
+        )));
+    }
+
+    let mut excluded = Vec::with_capacity(payload.batch.len());
+    let mut messages = Vec::with_capacity(payload.batch.len());
+
+    for event in &payload.batch {
+        let channels = [event.channel.clone()];
+
+        validate_event(&request.app, &event.name, &channels, &event.data)?;
+
+        messages.push(encrypted_payload(
+            &request.app,
+            &channels,
+            event.data.clone(),
+        )?);
+
+        excluded.push(excluded_socket(&event.socket_id)?);
+    }
+
+    let with_info = payload.batch.iter().any(|event| event.info.is_some());
+
+    let ns = adapter.namespace(request.app.id);
+
+    let attributes = payload
+        .batch
+        .iter()
+        .map(|event| {
+            channel_attributes(
+                &*ns,
+                &Channel::from(event.channel.clone()),
+                event.info.as_deref(),
+            )
+        })
+        .collect::<Result<Vec<ChannelResult>, ApiError>>()?;
+
+    drop(ns);
+
+    for ((event, except), message) in payload.batch.into_iter().zip(excluded).zip(messages) {
+        handler.do_send(Broadcast {
+            channels: vec![event.channel],
+            event: event.name,
+            except,
+            message,
+            app: request.app.clone(),
+        });
+    }
+
+    if with_info {
+        Ok(HttpResponse::Ok().json(json!({ "batch": attributes })))
+    } else {
+        Ok(HttpResponse::Ok().json(json!({})))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::PusherMessageChannelData;
+    use crate::testing::{signed, TestServer};
+    use actix_web::http::Method;
+    use actix_web::{rt::System, test};
+    use serde_json::Value;
+
+    fn member(user_id: &str) -> Option<PusherMessageChannelData> {
+        Some(PusherMessageChannelData {
+            user_id: user_id.to_string(),
+            user_info: json!({}),
+        })
+    }
+
+    #[test]
+    fn batch_returns_requested_channel_attributes() {
+        System::new("events").block_on(async {
+            let app = App::new("events".to_string());
+            let server = TestServer::start(&[&app]);
+
+            {
+                let ns = server.adapter.namespace(app.id);
+                let room = Channel::from("presence-room".to_string());
+
+                ns.add_to_channel(1, &room, member("1"));
+                ns.add_to_channel(2, &room, member("1"));
+                ns.add_to_channel(3, &room, member("2"));
+            }
+
+            let mut service = test::init_service(
+                actix_web::App::new()
+                    .configure(server.data())
+                    .service(batch),
+            )
+            .await;
+
+            let body = json!({
+                "batch": [
+                    {
+                        "name": "moved",
+                        "channel": "presence-room",
+                        "data": "{}",
+                        "info": "subscription_count,user_count",
+                    },
+                    {"name": "moved", "channel": "lobby", "data": "{}"},
+                ]
+            })
+            .to_string();
+
+            let path = format!("/apps/{}/batch_events", app.id);
+            let request = signed(&app, Method::POST, &path, &[], &body).to_request();
+
+            let response: Value = test::read_response_json(&mut service, request).await;
+
+            assert_eq!(
+                json!({
+                    "batch": [{"subscription_count": 3, "user_count": 2}, {}]
+                }),
+                response
+            );
+        });
+    }
 }
@@ -1,7 +1,10 @@
-mod errors;
+pub mod admin;
 pub mod apps;
+pub mod auth;
 pub mod channels;
+pub mod errors;
 pub mod events;
+pub mod users;
 
 use actix_web::{get, HttpResponse, Responder};
 
@@ -0,0 +1,143 @@
+use actix::Addr;
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api::auth::SignedRequest;
+use crate::api::errors::ApiError;
+use crate::api::events::validate_event;
+use crate::kind::Channel;
+use crate::ws::{Broadcast, TerminateUserConnections};
+use crate::WebSocketHandler;
+
+#[derive(Debug, Deserialize)]
+pub struct UserPath {
+    pub user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserEvent {
+    pub name: String,
+    pub data: String,
+}
+
+/// Sends an event to every connection signed in as the user, on their
+/// `#server-to-user-` channel.
+#[post("/apps/{app_id}/users/{user_id}/events")]
+pub async fn events(
+    request: SignedRequest,
+    path: web::Path<UserPath>,
+    handler: web::Data<Addr<WebSocketHandler>>,
+) -> Result<HttpResponse, ApiError> {
+    let event: UserEvent = request.json()?;
+
+    let channel = Channel::server_to_user(&path.user_id).to_string();
+
+    validate_event(
+        &request.app,
+        &event.name,
+        std::slice::from_ref(&channel),
+        &event.data,
+    )?;
+
+    handler.do_send(Broadcast {
+        channels: vec![channel],
+        event: event.name,
+        except: None,
+        message: event.data,
+        app: request.app,
+    });
+
+    Ok(HttpResponse::Ok().json(json!({})))
+}
+
+/// Closes every connection signed in as the user with a 4009 `pusher:error`.
+#[post("/apps/{app_id}/users/{user_id}/terminate_connections")]
+pub async fn terminate_connections(
+    request: SignedRequest,
+    path: web::Path<UserPath>,
+    handler: web::Data<Addr<WebSocketHandler>>,
+) -> Result<HttpResponse, ApiError> {
+    handler.do_send(TerminateUserConnections {
+        app_id: request.app.id,
+        user_id: path.into_inner().user_id,
+    });
+
+    Ok(HttpResponse::Ok().json(json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use crate::auth::sign;
+    use crate::socket::Socket;
+    use crate::testing::{signed, Take, TestServer};
+    use actix_web::http::Method;
+    use actix_web::{rt::System, test};
+
+    #[test]
+    fn terminates_the_users_connections() {
+        System::new("users").block_on(async {
+            let app = App::new("users".to_string());
+            let server = TestServer::start(&[&app]);
+
+            let (user, user_client) = server.connect(&app).await;
+            let (_, other_client) = server.connect(&app).await;
+
+            let user_data = json!({"id": "1"}).to_string();
+            let signature = sign(
+                &app.secret,
+                &format!(
+                    "{}::user::{}",
+                    Socket { id: user.id }.to_string(),
+                    user_data
+                ),
+            );
+
+            server
+                .send(
+                    &user,
+                    json!({
+                        "event": "pusher:signin",
+                        "data": {
+                            "auth": format!("{}:{}", app.key, signature),
+                            "user_data": user_data,
+                        },
+                    }),
+                )
+                .await
+                .unwrap();
+
+            let mut service = test::init_service(
+                actix_web::App::new()
+                    .configure(server.data())
+                    .service(terminate_connections),
+            )
+            .await;
+
+            let path = format!("/apps/{}/users/1/terminate_connections", app.id);
+            let request = signed(&app, Method::POST, &path, &[], "").to_request();
+
+            assert!(test::call_service(&mut service, request)
+                .await
+                .status()
+                .is_success());
+
+            // the handler has terminated the connections once it answers a ping
+            server
+                .send(&user, json!({"event": "pusher:ping", "data": {}}))
+                .await
+                .unwrap();
+
+            let terminated = |received: Vec<serde_json::Value>| {
+                received.iter().any(|message| {
+                    message["event"] == "pusher:error" && message["data"]["code"] == 4009
+                })
+            };
+
+            assert!(terminated(user_client.send(Take).await.unwrap()));
+            assert!(!terminated(other_client.send(Take).await.unwrap()));
+        });
+    }
+}
@@ -1,12 +1,68 @@
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Serialize;
 
+/// Pusher's limit on users subscribed to one presence channel.
+pub const DEFAULT_MAX_PRESENCE_MEMBERS: i32 = 100;
+
+/// Pusher's limit on the size of a presence member's `user_info`, in bytes.
+pub const DEFAULT_MAX_PRESENCE_USER_INFO_SIZE: i32 = 1024;
+
+/// Pusher's limit on the size of an event's data, in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: i32 = 10 * 1024;
+
 #[derive(Clone, Debug, Serialize)]
 pub struct App {
     pub id: i64,
     pub name: String,
     pub key: String,
     pub secret: String,
+    pub enable_client_events: bool,
+    pub webhook_url: Option<String>,
+    pub enable_client_event_webhooks: bool,
+    pub max_presence_members: i32,
+    pub max_presence_user_info_size: i32,
+    /// Base64 encoded key events on `private-encrypted-` channels are
+    /// encrypted with when triggered as plaintext.
+    pub encryption_master_key: Option<String>,
+    pub enable_watchlist_webhooks: bool,
+    pub enable_subscription_count: bool,
+    pub enabled: bool,
+    /// Concurrent connections allowed, unlimited when unset.
+    pub max_connections: Option<i32>,
+    /// Largest event `data` accepted from clients and the HTTP API, in bytes.
+    pub max_message_size: i32,
+    /// Names of the webhook events sent to `webhook_url`, all of them when empty.
+    pub webhook_events: Vec<String>,
+    /// `Origin` headers WebSocket connections are accepted from, any when empty.
+    pub allowed_origins: Vec<String>,
+    /// Key pairs accepted besides `key` and `secret`.
+    pub keys: Vec<AppKey>,
+}
+
+/// An additional key pair of an app. It is active until retired, after which
+/// it is still accepted until `expires_at`.
+#[derive(Clone, Debug, Serialize)]
+pub struct AppKey {
+    pub key: String,
+    pub secret: String,
+    /// Unix timestamp in seconds.
+    pub expires_at: Option<i64>,
+}
+
+impl AppKey {
+    pub fn generate() -> AppKey {
+        AppKey {
+            key: generate_public_key(),
+            secret: generate_secret_key(),
+            expires_at: None,
+        }
+    }
+
+    pub fn is_valid(&self, now: i64) -> bool {
+        self.expires_at
+            .map(|expires_at| now < expires_at)
+            .unwrap_or(true)
+    }
 }
 
 impl App {
@@ -16,7 +72,61 @@ impl App {
             name,
             key: generate_public_key(),
             secret: generate_secret_key(),
+            enable_client_events: false,
+            webhook_url: None,
+            enable_client_event_webhooks: false,
+            max_presence_members: DEFAULT_MAX_PRESENCE_MEMBERS,
+            max_presence_user_info_size: DEFAULT_MAX_PRESENCE_USER_INFO_SIZE,
+            encryption_master_key: None,
+            enable_watchlist_webhooks: false,
+            enable_subscription_count: false,
+            enabled: true,
+            max_connections: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            webhook_events: Vec::new(),
+            allowed_origins: Vec::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Replaces `key` and `secret` with a new pair and keeps the old one in
+    /// `keys` until `expires_at`, returning it.
+    pub fn rotate_key(&mut self, expires_at: i64) -> AppKey {
+        let new = AppKey::generate();
+
+        let retired = AppKey {
+            key: std::mem::replace(&mut self.key, new.key),
+            secret: std::mem::replace(&mut self.secret, new.secret),
+            expires_at: Some(expires_at),
+        };
+
+        self.keys.push(retired.clone());
+
+        retired
+    }
+
+    /// The secret signatures made with `key` are checked against, if it is
+    /// the app's own key or one of `keys` that has not expired at `now`.
+    pub fn secret_for(&self, key: &str, now: i64) -> Option<&str> {
+        if key == self.key {
+            return Some(self.secret.as_str());
         }
+
+        self.keys
+            .iter()
+            .find(|k| k.key == key && k.is_valid(now))
+            .map(|k| k.secret.as_str())
+    }
+
+    pub fn sends_webhook(&self, name: &str) -> bool {
+        self.webhook_events.is_empty() || self.webhook_events.iter().any(|e| e == name)
+    }
+
+    pub fn allows_origin(&self, origin: Option<&str>) -> bool {
+        self.allowed_origins.is_empty()
+            || origin
+                .map(|origin| self.allowed_origins.iter().any(|o| o == origin))
+                .unwrap_or(false)
     }
 }
 
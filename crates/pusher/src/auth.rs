@@ -1,7 +1,10 @@
 use crate::app::App;
 use hmac::{Hmac, Mac, NewMac};
+use md5::{Digest, Md5};
 use sha2::Sha256;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 pub struct AuthError(pub &'static str);
@@ -30,6 +33,13 @@ impl AuthPayload {
         }
     }
 
+    /// The payload of a `pusher:signin`, signed over `socket_id::user::user_data`.
+    pub fn signin(signature: String, id: String, user_data: String) -> AuthPayload {
+        // parts are joined with single colons, so the channel carries the
+        // separators around `user`
+        AuthPayload::new(signature, id, ":user:".to_string(), Some(user_data))
+    }
+
     pub fn parts(&self) -> Vec<String> {
         if let Some(channel_data) = self.channel_data.clone() {
             vec![self.id.clone(), self.channel.clone(), channel_data]
@@ -39,13 +49,29 @@ impl AuthPayload {
     }
 }
 
+/// Hex encoded HMAC-SHA256 of `message`, as used for webhook signatures.
+pub fn sign(secret: &str, message: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+
+    mac.update(message.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Verifies a `key:signature` auth string against the secret of the key pair
+/// it names.
 pub fn validate_token(app: &App, auth_payload: &AuthPayload) -> Result<(), AuthError> {
     let sig_components = auth_payload.signature.split(':').collect::<Vec<&str>>();
 
-    if sig_components.len() != 2 || sig_components[0] != app.key {
+    if sig_components.len() != 2 {
         return Err(AuthError("invalid signature"));
     };
 
+    let secret = app
+        .secret_for(sig_components[0], now())
+        .ok_or(AuthError("invalid signature"))?;
+
     let decoded_signature = hex::decode(sig_components[1].as_bytes()).unwrap_or_default();
 
     let message = auth_payload
@@ -55,7 +81,7 @@ pub fn validate_token(app: &App, auth_payload: &AuthPayload) -> Result<(), AuthE
         .collect::<Vec<String>>()
         .join(":");
 
-    match HmacSha256::new_from_slice(app.secret.as_bytes()) {
+    match HmacSha256::new_from_slice(secret.as_bytes()) {
         Ok(mut mac) => {
             mac.update(message.as_bytes());
 
@@ -69,39 +95,242 @@ pub fn validate_token(app: &App, auth_payload: &AuthPayload) -> Result<(), AuthE
     }
 }
 
+/// A signed Pusher HTTP API request, see
+/// https://pusher.com/docs/channels/library_auth_reference/rest-api/#authentication
+pub struct RequestPayload<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub params: &'a BTreeMap<String, String>,
+    pub body: &'a [u8],
+}
+
+impl<'a> RequestPayload<'a> {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|v| v.as_str())
+    }
+
+    /// The string covered by `auth_signature`: the method, the path and every
+    /// query parameter except the signature itself, sorted by key.
+    pub fn string_to_sign(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .filter(|(k, _)| k.as_str() != "auth_signature")
+            .map(|(k, v)| (k.to_lowercase(), v))
+            .collect::<BTreeMap<String, &String>>();
+
+        let query = params
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<String>>()
+            .join("&");
+
+        format!("{}\n{}\n{}", self.method.to_uppercase(), self.path, query)
+    }
+}
+
+pub fn validate_request(
+    app: &App,
+    payload: &RequestPayload,
+    now: u64,
+    grace: u64,
+) -> Result<(), AuthError> {
+    let secret = payload
+        .param("auth_key")
+        .and_then(|key| app.secret_for(key, now as i64))
+        .ok_or(AuthError("invalid auth_key"))?;
+
+    if payload.param("auth_version") != Some("1.0") {
+        return Err(AuthError("unsupported auth_version"));
+    }
+
+    let timestamp = payload
+        .param("auth_timestamp")
+        .and_then(|t| t.parse::<u64>().ok())
+        .ok_or(AuthError("invalid auth_timestamp"))?;
+
+    if now.max(timestamp) - now.min(timestamp) > grace {
+        return Err(AuthError("auth_timestamp expired"));
+    }
+
+    match payload.param("body_md5") {
+        Some(body_md5) if hex::encode(Md5::digest(payload.body)) != body_md5 => {
+            return Err(AuthError("body_md5 does not match"));
+        }
+        None if !payload.body.is_empty() => return Err(AuthError("missing body_md5")),
+        _ => (),
+    }
+
+    let signature = payload
+        .param("auth_signature")
+        .ok_or(AuthError("missing auth_signature"))?;
+
+    let decoded_signature = hex::decode(signature.as_bytes()).unwrap_or_default();
+
+    match HmacSha256::new_from_slice(secret.as_bytes()) {
+        Ok(mut mac) => {
+            mac.update(payload.string_to_sign().as_bytes());
+
+            if mac.verify(decoded_signature.as_slice()).is_ok() {
+                Ok(())
+            } else {
+                Err(AuthError("invalid auth_signature"))
+            }
+        }
+        Err(_e) => Err(AuthError("failed to create hash")),
+    }
+}
+
+/// Current unix time in seconds.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::app::AppKey;
+
+    /// An app with the key pair used in Pusher's authentication examples.
+    fn documented_app(id: i64) -> App {
+        App {
+            id,
+            key: "278d425bdf160c739803".to_string(),
+            secret: "7ad3773142a6692b25b8".to_string(),
+            ..App::new("test".to_string())
+        }
+    }
 
     #[test]
     fn create_new_key() {
-        todo!();
-        // let key = Key::generate();
-        //
-        // assert_eq!(24, key.public.len());
-        // assert!(!key.private.is_empty());
+        let app = App::new("test".to_string());
+
+        assert_eq!(24, app.key.len());
+        assert!(!app.secret.is_empty());
+        assert_ne!(app.key, App::new("test".to_string()).key);
     }
 
     #[test]
     fn validates_signatures() {
-        // let app = App {
-        //     id: "".to_string(),
-        //     key: "278d425bdf160c739803".to_string(),
-        //     secret: "7ad3773142a6692b25b8".to_string(),
-        // };
-        //
-        // let signature =
-        //     "278d425bdf160c739803:58df8b0c36d6982b82c3ecf6b4662e34fe8c25bba48f5369f135bf843651c3a4"
-        //         .to_string();
-        //
-        // let auth_payload = AuthPayload::new(
-        //     signature,
-        //     "1234.1234".to_string(),
-        //     "private-foobar".to_string(),
-        //     None,
-        // );
-        //
-        // let res = validate_token(&app, &auth_payload);
-        //
-        // assert!(res.is_ok());
+        let app = documented_app(1);
+
+        let signature =
+            "278d425bdf160c739803:58df8b0c36d6982b82c3ecf6b4662e34fe8c25bba48f5369f135bf843651c3a4"
+                .to_string();
+
+        let auth_payload = AuthPayload::new(
+            signature,
+            "1234.1234".to_string(),
+            "private-foobar".to_string(),
+            None,
+        );
+
+        let res = validate_token(&app, &auth_payload);
+
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn validates_request_signatures() {
+        let app = documented_app(3);
+
+        let body = br#"{"name":"foo","channels":["project-3"],"data":"{\"some\":\"data\"}"}"#;
+
+        let params: BTreeMap<String, String> = [
+            ("auth_key", "278d425bdf160c739803"),
+            ("auth_timestamp", "1353088179"),
+            ("auth_version", "1.0"),
+            ("body_md5", "ec365a775a4cd0599faeb73354201b6f"),
+            (
+                "auth_signature",
+                "da454824c97ba181a32ccc17a72625ba02771f50b50e1e7430e47a1f3f457e6c",
+            ),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let payload = RequestPayload {
+            method: "POST",
+            path: "/apps/3/events",
+            params: &params,
+            body,
+        };
+
+        assert_eq!(
+            "da454824c97ba181a32ccc17a72625ba02771f50b50e1e7430e47a1f3f457e6c",
+            sign(&app.secret, &payload.string_to_sign())
+        );
+
+        assert!(validate_request(&app, &payload, 1353088179, 600).is_ok());
+        assert!(validate_request(&app, &payload, 1353088179 + 601, 600).is_err());
+
+        let tampered = RequestPayload {
+            body: br#"{"name":"bar"}"#,
+            ..payload
+        };
+
+        assert!(validate_request(&app, &tampered, 1353088179, 600).is_err());
+    }
+
+    #[test]
+    fn validates_signin_signatures() {
+        let app = App::new("test".to_string());
+
+        let user_data = r#"{"id":"1"}"#.to_string();
+
+        let signature = |message: &str| format!("{}:{}", app.key, sign(&app.secret, message));
+
+        let valid = AuthPayload::signin(
+            signature(r#"1234.1234::user::{"id":"1"}"#),
+            "1234.1234".to_string(),
+            user_data.clone(),
+        );
+
+        assert!(validate_token(&app, &valid).is_ok());
+
+        let invalid = AuthPayload::signin(
+            signature(r#"1234.1234:user:{"id":"1"}"#),
+            "1234.1234".to_string(),
+            user_data,
+        );
+
+        assert!(validate_token(&app, &invalid).is_err());
+    }
+
+    #[test]
+    fn validates_signatures_of_additional_keys() {
+        let mut app = App::new("test".to_string());
+
+        let active = AppKey::generate();
+        let expired = AppKey {
+            expires_at: Some(1),
+            ..AppKey::generate()
+        };
+
+        app.keys = vec![active.clone(), expired.clone()];
+
+        let payload = |key: &AppKey| {
+            AuthPayload::new(
+                format!(
+                    "{}:{}",
+                    key.key,
+                    sign(&key.secret, "1234.1234:private-foobar")
+                ),
+                "1234.1234".to_string(),
+                "private-foobar".to_string(),
+                None,
+            )
+        };
+
+        assert!(validate_token(&app, &payload(&active)).is_ok());
+        assert!(validate_token(&app, &payload(&expired)).is_err());
+
+        let retired = app.rotate_key(now() + 60);
+
+        assert!(validate_token(&app, &payload(&retired)).is_ok());
     }
 }
@@ -0,0 +1,146 @@
+use std::str::FromStr;
+
+use log::warn;
+
+/// Allowed drift between a request's `auth_timestamp` and the server clock, in seconds.
+const DEFAULT_AUTH_TIMESTAMP_GRACE: u64 = 600;
+
+/// How long the last event on a cache channel is kept, in seconds.
+const DEFAULT_CACHE_CHANNEL_TTL: u64 = 30 * 60;
+
+/// Minimum time between two `pusher_internal:subscription_count` events for
+/// a channel, in milliseconds.
+const DEFAULT_SUBSCRIPTION_COUNT_INTERVAL_MS: u64 = 5000;
+
+/// How long webhook events for an app are collected into one request, in milliseconds.
+const DEFAULT_WEBHOOK_BATCH_WINDOW_MS: u64 = 250;
+
+/// Delay before the first webhook retry, doubled on every further attempt, in milliseconds.
+const DEFAULT_WEBHOOK_RETRY_BACKOFF_MS: u64 = 1000;
+
+/// Delivery attempts before a webhook is moved to the dead-letter table.
+const DEFAULT_WEBHOOK_MAX_ATTEMPTS: i32 = 8;
+
+/// What an admin API token may do. `Manage` includes everything `Read` allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AdminScope {
+    Read,
+    Manage,
+}
+
+/// A static bearer token for the admin API.
+#[derive(Clone, Debug)]
+pub struct AdminToken {
+    pub token: String,
+    pub scope: AdminScope,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub auth_timestamp_grace: u64,
+    pub cache_channel_ttl: u64,
+    pub subscription_count_interval_ms: u64,
+    pub webhook_batch_window_ms: u64,
+    pub webhook_retry_backoff_ms: u64,
+    pub webhook_max_attempts: i32,
+    /// The admin API rejects every request while this is empty.
+    pub admin_tokens: Vec<AdminToken>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            auth_timestamp_grace: DEFAULT_AUTH_TIMESTAMP_GRACE,
+            cache_channel_ttl: DEFAULT_CACHE_CHANNEL_TTL,
+            subscription_count_interval_ms: DEFAULT_SUBSCRIPTION_COUNT_INTERVAL_MS,
+            webhook_batch_window_ms: DEFAULT_WEBHOOK_BATCH_WINDOW_MS,
+            webhook_retry_backoff_ms: DEFAULT_WEBHOOK_RETRY_BACKOFF_MS,
+            webhook_max_attempts: DEFAULT_WEBHOOK_MAX_ATTEMPTS,
+            admin_tokens: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            auth_timestamp_grace: env_or(
+                "PUSHER_AUTH_TIMESTAMP_GRACE",
+                defaults.auth_timestamp_grace,
+            ),
+            cache_channel_ttl: env_or("PUSHER_CACHE_CHANNEL_TTL", defaults.cache_channel_ttl),
+            subscription_count_interval_ms: env_or(
+                "PUSHER_SUBSCRIPTION_COUNT_INTERVAL_MS",
+                defaults.subscription_count_interval_ms,
+            ),
+            webhook_batch_window_ms: env_or(
+                "PUSHER_WEBHOOK_BATCH_WINDOW_MS",
+                defaults.webhook_batch_window_ms,
+            ),
+            webhook_retry_backoff_ms: env_or(
+                "PUSHER_WEBHOOK_RETRY_BACKOFF_MS",
+                defaults.webhook_retry_backoff_ms,
+            ),
+            webhook_max_attempts: env_or(
+                "PUSHER_WEBHOOK_MAX_ATTEMPTS",
+                defaults.webhook_max_attempts,
+            ),
+            admin_tokens: std::env::var("PUSHER_ADMIN_TOKENS")
+                .map(|tokens| parse_admin_tokens(&tokens))
+                .unwrap_or(defaults.admin_tokens),
+        }
+    }
+}
+
+/// Parses a comma separated list of `token:scope` pairs, where the scope is
+/// `read` or `manage`. Malformed entries are skipped.
+fn parse_admin_tokens(tokens: &str) -> Vec<AdminToken> {
+    tokens
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let token = match entry.rsplit_once(':') {
+                Some((token, "read")) if !token.is_empty() => AdminToken {
+                    token: token.to_string(),
+                    scope: AdminScope::Read,
+                },
+                Some((token, "manage")) if !token.is_empty() => AdminToken {
+                    token: token.to_string(),
+                    scope: AdminScope::Manage,
+                },
+                _ => {
+                    warn!("ignoring admin token without a read or manage scope");
+                    return None;
+                }
+            };
+
+            Some(token)
+        })
+        .collect()
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_admin_tokens() {
+        let tokens = parse_admin_tokens("abc:manage, def:read,ghi,:read,jkl:write");
+
+        assert_eq!(2, tokens.len());
+        assert_eq!("abc", tokens[0].token);
+        assert_eq!(AdminScope::Manage, tokens[0].scope);
+        assert_eq!("def", tokens[1].token);
+        assert_eq!(AdminScope::Read, tokens[1].scope);
+    }
+}
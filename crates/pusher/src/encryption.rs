@@ -0,0 +1,110 @@
+use crypto_secretbox::aead::{Aead, KeyInit};
+use crypto_secretbox::{Key, Nonce, XSalsa20Poly1305};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Length in bytes of an app's encryption master key.
+pub const MASTER_KEY_LENGTH: usize = 32;
+
+/// The payload pusher-js expects on `private-encrypted-` channels, with both
+/// fields base64 encoded.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptedData {
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Decodes a base64 encoded master key, checking its length.
+pub fn decode_master_key(master_key: &str) -> Result<Vec<u8>, &'static str> {
+    match base64::decode(master_key) {
+        Ok(key) if key.len() == MASTER_KEY_LENGTH => Ok(key),
+        Ok(_) => Err("encryption master key must be 32 bytes"),
+        Err(_) => Err("encryption master key must be base64 encoded"),
+    }
+}
+
+/// Whether `data` is already a `{nonce, ciphertext}` payload.
+pub fn is_encrypted(data: &str) -> bool {
+    serde_json::from_str::<EncryptedData>(data)
+        .map(|data| base64::decode(data.nonce).is_ok() && base64::decode(data.ciphertext).is_ok())
+        .unwrap_or(false)
+}
+
+/// The channel's secretbox key, derived the same way the server SDKs and
+/// auth endpoints derive the `shared_secret`: SHA-256 of the channel name
+/// followed by the master key.
+pub fn shared_secret(channel: &str, master_key: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    hasher.update(channel.as_bytes());
+    hasher.update(master_key);
+
+    hasher.finalize().into()
+}
+
+/// Encrypts `data` for the channel with a random nonce.
+pub fn encrypt(channel: &str, master_key: &[u8], data: &str) -> EncryptedData {
+    let key = shared_secret(channel, master_key);
+    let nonce = thread_rng().gen::<[u8; 24]>();
+
+    let ciphertext = XSalsa20Poly1305::new(&Key::from(key))
+        .encrypt(&Nonce::from(nonce), data.as_bytes())
+        .expect("secretbox encryption does not fail");
+
+    EncryptedData {
+        nonce: base64::encode(nonce),
+        ciphertext: base64::encode(ciphertext),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypts_for_the_channel_secret() {
+        let master_key = [7u8; MASTER_KEY_LENGTH];
+
+        let encrypted = encrypt("private-encrypted-room", &master_key, "{\"a\":1}");
+
+        assert!(is_encrypted(&serde_json::to_string(&encrypted).unwrap()));
+
+        let nonce: [u8; 24] = base64::decode(&encrypted.nonce)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let ciphertext = base64::decode(&encrypted.ciphertext).unwrap();
+
+        let key = shared_secret("private-encrypted-room", &master_key);
+        let plaintext = XSalsa20Poly1305::new(&Key::from(key))
+            .decrypt(&Nonce::from(nonce), ciphertext.as_slice())
+            .unwrap();
+
+        assert_eq!(b"{\"a\":1}".to_vec(), plaintext);
+
+        let other = shared_secret("private-encrypted-other", &master_key);
+        assert!(XSalsa20Poly1305::new(&Key::from(other))
+            .decrypt(&Nonce::from(nonce), ciphertext.as_slice())
+            .is_err());
+    }
+
+    #[test]
+    fn recognises_encrypted_payloads() {
+        assert!(is_encrypted(r#"{"nonce":"AAAA","ciphertext":"AAAA"}"#));
+        assert!(!is_encrypted(r#"{"nonce":"AAAA"}"#));
+        assert!(!is_encrypted(
+            r#"{"nonce":"not base64!","ciphertext":"AAAA"}"#
+        ));
+        assert!(!is_encrypted(r#"{"message":"hello"}"#));
+        assert!(!is_encrypted("hello"));
+    }
+
+    #[test]
+    fn validates_master_keys() {
+        assert!(decode_master_key(&base64::encode([1u8; 32])).is_ok());
+        assert!(decode_master_key(&base64::encode([1u8; 16])).is_err());
+        assert!(decode_master_key("not base64!").is_err());
+    }
+}
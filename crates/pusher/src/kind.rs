@@ -11,20 +11,55 @@ pub struct WebSocket {
     pub app_id: i64,
 }
 
+/// Prefix of the channel events for a signed in user are sent on.
+pub const SERVER_TO_USER_PREFIX: &str = "#server-to-user-";
+
 #[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize)]
 pub enum Channel {
     Presence(String),
     Private(String),
+    PrivateEncrypted(String),
+    Cache(String),
     Public(String),
+    ServerToUser(String),
     Invalid,
 }
 
+impl Channel {
+    pub fn server_to_user(user_id: &str) -> Self {
+        Channel::ServerToUser(format!("{}{}", SERVER_TO_USER_PREFIX, user_id))
+    }
+
+    /// The user a `#server-to-user-` channel belongs to.
+    pub fn user_id(&self) -> Option<&str> {
+        match self {
+            Channel::ServerToUser(s) => s.strip_prefix(SERVER_TO_USER_PREFIX),
+            _ => None,
+        }
+    }
+
+    /// Whether the last event published on the channel is kept for new
+    /// subscribers, as on `cache-`, `private-cache-` and `presence-cache-` channels.
+    pub fn is_cache(&self) -> bool {
+        match self {
+            Channel::Cache(_) => true,
+            Channel::Private(s) => s.starts_with("private-cache-"),
+            Channel::PrivateEncrypted(s) => s.starts_with("private-encrypted-cache-"),
+            Channel::Presence(s) => s.starts_with("presence-cache-"),
+            _ => false,
+        }
+    }
+}
+
 impl ToString for Channel {
     fn to_string(&self) -> String {
         match self {
             Channel::Presence(s) => s.clone(),
             Channel::Private(s) => s.clone(),
+            Channel::PrivateEncrypted(s) => s.clone(),
+            Channel::Cache(s) => s.clone(),
             Channel::Public(s) => s.clone(),
+            Channel::ServerToUser(s) => s.clone(),
             Channel::Invalid => "".to_string(),
         }
     }
@@ -43,8 +78,14 @@ impl From<String> for Channel {
     fn from(s: String) -> Self {
         if s.starts_with("presence-") {
             Self::Presence(s)
+        } else if s.starts_with("private-encrypted-") {
+            Self::PrivateEncrypted(s)
         } else if s.starts_with("private-") {
             Self::Private(s)
+        } else if s.starts_with(SERVER_TO_USER_PREFIX) {
+            Self::ServerToUser(s)
+        } else if s.starts_with("cache-") {
+            Self::Cache(s)
         } else {
             Self::Public(s)
         }
@@ -56,6 +97,7 @@ pub enum Event {
     Ping,
     Subscribe,
     Unsubscribe,
+    Signin,
     Client(String),
     Invalid,
 }
@@ -75,7 +117,49 @@ impl From<String> for Event {
             "pusher:ping" => Self::Ping,
             "pusher:subscribe" => Self::Subscribe,
             "pusher:unsubscribe" => Self::Unsubscribe,
-            _ => Self::Client(s.clone()),
+            "pusher:signin" => Self::Signin,
+            _ if s.starts_with("client-") => Self::Client(s.clone()),
+            _ => Self::Invalid,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_cache_channels() {
+        assert_eq!(
+            Channel::Cache("cache-room".to_string()),
+            Channel::from("cache-room".to_string())
+        );
+
+        assert!(Channel::from("cache-room".to_string()).is_cache());
+        assert!(Channel::from("private-cache-room".to_string()).is_cache());
+        assert!(Channel::from("presence-cache-room".to_string()).is_cache());
+        assert!(!Channel::from("private-room".to_string()).is_cache());
+        assert!(!Channel::from("room-cache-".to_string()).is_cache());
+    }
+
+    #[test]
+    fn recognises_encrypted_channels() {
+        assert_eq!(
+            Channel::PrivateEncrypted("private-encrypted-room".to_string()),
+            Channel::from("private-encrypted-room".to_string())
+        );
+
+        assert!(Channel::from("private-encrypted-cache-room".to_string()).is_cache());
+        assert!(!Channel::from("private-encrypted-room".to_string()).is_cache());
+    }
+
+    #[test]
+    fn recognises_server_to_user_channels() {
+        let channel = Channel::from("#server-to-user-42".to_string());
+
+        assert_eq!(Channel::server_to_user("42"), channel);
+        assert_eq!(Some("42"), channel.user_id());
+        assert_eq!("#server-to-user-42", channel.to_string());
+        assert_eq!(None, Channel::from("private-42".to_string()).user_id());
+    }
+}
@@ -6,10 +6,12 @@ extern crate pusher_message_derive;
 
 use crate::adapter::{Adapter, InMemoryAdapter};
 use crate::app::App as PusherApp;
+use crate::config::Config;
 use crate::kind::WebSocket;
 use crate::messages::OutgoingMessage;
 use crate::repository::sqlite::SqliteRepo;
-use crate::repository::AppRepo;
+use crate::repository::{AppRepo, WebhookRepo};
+use crate::webhooks::WebhookDispatcher;
 use crate::ws::WebSocketHandler;
 use crate::ws_handler::Session;
 use actix::{Actor, Addr};
@@ -27,11 +29,16 @@ mod adapter;
 mod api;
 mod app;
 mod auth;
+mod config;
+mod encryption;
 mod kind;
 mod messages;
 mod namespace;
 mod repository;
 mod socket;
+#[cfg(test)]
+mod testing;
+mod webhooks;
 mod ws;
 mod ws_handler;
 
@@ -40,13 +47,26 @@ async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "actix_web=debug");
     env_logger::init();
 
-    let repo: Arc<PMutex<dyn AppRepo>> = Arc::new(PMutex::new(SqliteRepo::new(
+    let config = Config::from_env();
+
+    let sqlite = Arc::new(PMutex::new(SqliteRepo::new(
         SqliteConnection::establish("./tmp.db").unwrap(),
     )));
 
+    let repo: Arc<PMutex<dyn AppRepo>> = sqlite.clone();
+    let outbox: Arc<PMutex<dyn WebhookRepo>> = sqlite;
+
     let adapter: Arc<dyn Adapter> = Arc::new(InMemoryAdapter::default());
 
-    let handler = WebSocketHandler::new(adapter.clone(), repo.clone()).start();
+    let webhooks = WebhookDispatcher::new(repo.clone(), outbox.clone(), config.clone()).start();
+
+    let handler = WebSocketHandler::new(
+        adapter.clone(),
+        repo.clone(),
+        webhooks.recipient(),
+        config.clone(),
+    )
+    .start();
 
     HttpServer::new(move || {
         App::new()
@@ -54,12 +74,26 @@ async fn main() -> std::io::Result<()> {
             .data(handler.clone())
             .data(adapter.clone())
             .data(repo.clone())
+            .data(outbox.clone())
+            .data(config.clone())
             .service(web::resource("/app/{app_id}").to(connect))
             .service(api::index)
             .service(api::apps::all)
             .service(api::apps::create)
+            .service(api::apps::show)
+            .service(api::apps::update)
+            .service(api::apps::delete)
+            .service(api::apps::rotate_secret)
+            .service(api::apps::add_key)
+            .service(api::apps::retire_key)
+            .service(api::apps::dead_letters)
             .service(api::events::publish)
+            .service(api::events::batch)
             .service(api::channels::all)
+            .service(api::channels::show)
+            .service(api::channels::users)
+            .service(api::users::events)
+            .service(api::users::terminate_connections)
     })
     .bind("0.0.0.0:9911")?
     .run()
@@ -77,8 +111,14 @@ async fn connect(
     stream: web::Payload,
     handler: web::Data<Addr<WebSocketHandler>>,
 ) -> impl Responder {
+    let origin = req
+        .headers()
+        .get("origin")
+        .and_then(|origin| origin.to_str().ok())
+        .map(|origin| origin.to_string());
+
     actix_ws::start(
-        Session::new(query.app_id, handler.get_ref().clone()),
+        Session::new(query.app_id, origin, handler.get_ref().clone()),
         &req,
         stream,
     )
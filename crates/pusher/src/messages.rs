@@ -1,6 +1,6 @@
 use actix::prelude::*;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -25,14 +25,45 @@ pub struct PusherMessage {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PusherMessageData {
-    #[serde(with = "serde_with::json::nested", default)]
-    pub channel_data: Option<PusherMessageChannelData>,
+    pub channel_data: Option<String>,
     pub channel: Option<String>,
     pub auth: Option<String>,
+    pub user_data: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PusherMessageChannelData {
+    #[serde(deserialize_with = "deserialize_string_from_number")]
     pub user_id: String,
     pub user_info: serde_json::Value,
 }
+
+/// The `user_data` a connection signs in with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PusherSigninUserData {
+    pub id: String,
+    /// Users whose online status is sent to this user.
+    #[serde(default)]
+    pub watchlist: Vec<String>,
+}
+
+/// Accepts ids sent as JSON numbers as well as strings, clients are not
+/// consistent about which one they use for `user_id`.
+pub fn deserialize_string_from_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(i64),
+        Float(f64),
+    }
+
+    match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(s) => Ok(s),
+        StringOrNumber::Number(i) => Ok(i.to_string()),
+        StringOrNumber::Float(f) => Ok(f.to_string()),
+    }
+}
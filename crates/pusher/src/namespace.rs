@@ -3,20 +3,47 @@
 use std::collections::{HashMap, HashSet};
 
 use std::sync::RwLock;
+use std::time::{Duration, Instant};
 
 
 use crate::kind::Channel;
 use crate::messages::PusherMessageChannelData;
 
 
+/// A presence channel member and the sockets they are subscribed with.
+#[derive(Debug)]
+struct PresenceMember {
+    data: PusherMessageChannelData,
+    sockets: HashSet<usize>,
+}
+
+/// Presence members of one channel, keyed by `user_id`.
+#[derive(Debug, Default)]
+struct PresenceChannel {
+    members: HashMap<String, PresenceMember>,
+    users: HashMap<usize, String>,
+}
+
+/// The last event published on a cache channel.
+#[derive(Clone, Debug)]
+pub struct CachedEvent {
+    pub event: String,
+    pub data: String,
+    cached_at: Instant,
+}
+
 #[derive(Debug)]
 pub struct Namespace<R>
 where R: Clone
 {
     sockets: RwLock<HashMap<usize, R>>,
     channels: RwLock<HashMap<Channel, HashSet<usize>>>,
-    channel_presence_data:
-    RwLock<HashMap<Channel, HashMap<usize, Option<PusherMessageChannelData>>>>,
+    channel_presence_data: RwLock<HashMap<Channel, PresenceChannel>>,
+    cached_events: RwLock<HashMap<Channel, CachedEvent>>,
+    users: RwLock<HashMap<String, HashSet<usize>>>,
+    socket_users: RwLock<HashMap<usize, String>>,
+    watchlists: RwLock<HashMap<String, HashSet<String>>>,
+    watchers: RwLock<HashMap<String, HashSet<String>>>,
 }
 
 impl<R> Default for Namespace<R> where R: Clone {
@@ -25,6 +52,11 @@ impl<R> Default for Namespace<R> where R: Clone {
             sockets: RwLock::default(),
             channels: RwLock::default(),
             channel_presence_data: RwLock::default(),
+            cached_events: RwLock::default(),
+            users: RwLock::default(),
+            socket_users: RwLock::default(),
+            watchlists: RwLock::default(),
+            watchers: RwLock::default(),
         }
     }
 }
@@ -36,6 +68,14 @@ where R: Clone
         self.sockets.write().unwrap().insert(id, ws);
     }
 
+    pub fn socket_ids(&self) -> Vec<usize> {
+        self.sockets.read().unwrap().keys().copied().collect()
+    }
+
+    pub fn socket_count(&self) -> usize {
+        self.sockets.read().unwrap().len()
+    }
+
     pub fn channels(&self) -> Vec<Channel> {
         self.channels
             .read()
@@ -50,12 +90,11 @@ where R: Clone
             .read()
             .unwrap()
             .get(channel)
-            .unwrap()
-            .keys()
-            .len()
+            .map(|presence| presence.members.len())
+            .unwrap_or(0)
     }
 
-    fn member_count_by_channel(&self, ch: &Channel) -> usize {
+    pub fn member_count_by_channel(&self, ch: &Channel) -> usize {
         if let Some(members) = self.channels.read().unwrap().get(&ch) {
             members.len()
         } else {
@@ -77,41 +116,76 @@ where R: Clone
             .collect()
     }
 
+    /// Adds the socket to the channel, returning how many members it has now
+    /// or `None` if the socket was already subscribed.
     pub fn add_to_channel(
         &self,
         id: usize,
         channel: &Channel,
         presence_data: Option<PusherMessageChannelData>,
-    ) -> usize {
+    ) -> Option<usize> {
         let mut channels = self.channels.write().unwrap();
 
         channels.entry(channel.clone()).or_default();
 
         let members = channels.get_mut(channel).unwrap();
 
-        members.insert(id);
+        let inserted = members.insert(id);
 
         let count = members.len();
 
         drop(channels);
 
-        self.channel_presence_data
-            .write()
-            .unwrap()
-            .entry(channel.clone())
-            .or_default()
-            .insert(id, presence_data);
+        if let Some(presence_data) = presence_data {
+            self.add_presence_member(id, channel, presence_data);
+        }
+
+        if inserted {
+            Some(count)
+        } else {
+            None
+        }
+    }
 
-        count
+    /// Adds the socket to the user's presence entry, keeping the user_info of
+    /// the user's first socket. A socket subscribing again as another user
+    /// leaves the entry of the user it was subscribed as.
+    fn add_presence_member(&self, id: usize, channel: &Channel, data: PusherMessageChannelData) {
+        let mut cpd = self.channel_presence_data.write().unwrap();
+
+        let presence = cpd.entry(channel.clone()).or_default();
+
+        if let Some(previous) = presence.users.insert(id, data.user_id.clone()) {
+            if let Some(member) = presence.members.get_mut(&previous) {
+                member.sockets.remove(&id);
+
+                if member.sockets.is_empty() {
+                    presence.members.remove(&previous);
+                }
+            }
+        }
+
+        presence
+            .members
+            .entry(data.user_id.clone())
+            .or_insert_with(|| PresenceMember {
+                data,
+                sockets: HashSet::new(),
+            })
+            .sockets
+            .insert(id);
     }
 
-    pub fn remove_from_channel(&self, id: usize, channel: &Channel) {
+    /// Removes the socket from the channel, returning how many members remain
+    /// or `None` if the socket wasn't subscribed.
+    pub fn remove_from_channel(&self, id: usize, channel: &Channel) -> Option<usize> {
         let mut channels = self.channels.write().unwrap();
 
         let mut len = 0;
+        let mut removed = false;
 
         if let Some(hs) = channels.get_mut(&channel) {
-            hs.remove(&id);
+            removed = hs.remove(&id);
 
             len = hs.len();
         }
@@ -120,7 +194,170 @@ where R: Clone
             channels.remove(&channel);
         }
 
+        drop(channels);
+
         self.remove_presence_data(id, channel);
+
+        if removed {
+            Some(len)
+        } else {
+            None
+        }
+    }
+
+    /// Whether any socket in the channel is subscribed as `user_id`.
+    pub fn has_user(&self, channel: &Channel, user_id: &str) -> bool {
+        self.channel_presence_data
+            .read()
+            .unwrap()
+            .get(channel)
+            .map(|presence| presence.members.contains_key(user_id))
+            .unwrap_or(false)
+    }
+
+    pub fn cache_event(&self, channel: &Channel, event: String, data: String) {
+        self.cached_events.write().unwrap().insert(
+            channel.clone(),
+            CachedEvent {
+                event,
+                data,
+                cached_at: Instant::now(),
+            },
+        );
+    }
+
+    /// The channel's last event, unless it is older than `ttl`.
+    pub fn cached_event(&self, channel: &Channel, ttl: Duration) -> Option<CachedEvent> {
+        let mut cached_events = self.cached_events.write().unwrap();
+
+        match cached_events.get(channel) {
+            Some(cached) if cached.cached_at.elapsed() < ttl => Some(cached.clone()),
+            Some(_) => {
+                cached_events.remove(channel);
+
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Associates the socket with a signed in user, returning whether it is
+    /// the user's first socket.
+    pub fn sign_in(&self, id: usize, user_id: &str) -> bool {
+        if self.user_of(id).as_deref() == Some(user_id) {
+            return false;
+        }
+
+        self.sign_out(id);
+
+        self.socket_users
+            .write()
+            .unwrap()
+            .insert(id, user_id.to_string());
+
+        let mut users = self.users.write().unwrap();
+
+        let sockets = users.entry(user_id.to_string()).or_default();
+
+        sockets.insert(id);
+
+        sockets.len() == 1
+    }
+
+    /// Forgets the socket's user, returning the user it was signed in as.
+    pub fn sign_out(&self, id: usize) -> Option<String> {
+        let user_id = self.socket_users.write().unwrap().remove(&id)?;
+
+        let mut users = self.users.write().unwrap();
+
+        let last_socket = users
+            .get_mut(&user_id)
+            .map(|sockets| {
+                sockets.remove(&id);
+
+                sockets.is_empty()
+            })
+            .unwrap_or(false);
+
+        if last_socket {
+            users.remove(&user_id);
+        }
+
+        Some(user_id)
+    }
+
+    /// The user the socket is signed in as.
+    pub fn user_of(&self, id: usize) -> Option<String> {
+        self.socket_users.read().unwrap().get(&id).cloned()
+    }
+
+    pub fn is_online(&self, user_id: &str) -> bool {
+        self.users.read().unwrap().contains_key(user_id)
+    }
+
+    /// Replaces the users `user_id` is watching.
+    pub fn set_watchlist(&self, user_id: &str, watchlist: &[String]) {
+        self.remove_watchlist(user_id);
+
+        let mut watchers = self.watchers.write().unwrap();
+
+        for watched in watchlist {
+            watchers
+                .entry(watched.clone())
+                .or_default()
+                .insert(user_id.to_string());
+        }
+
+        self.watchlists
+            .write()
+            .unwrap()
+            .insert(user_id.to_string(), watchlist.iter().cloned().collect());
+    }
+
+    pub fn remove_watchlist(&self, user_id: &str) {
+        let watchlist = match self.watchlists.write().unwrap().remove(user_id) {
+            Some(watchlist) => watchlist,
+            None => return,
+        };
+
+        let mut watchers = self.watchers.write().unwrap();
+
+        for watched in watchlist {
+            let unwatched = watchers
+                .get_mut(&watched)
+                .map(|users| {
+                    users.remove(user_id);
+
+                    users.is_empty()
+                })
+                .unwrap_or(false);
+
+            if unwatched {
+                watchers.remove(&watched);
+            }
+        }
+    }
+
+    /// Users with `user_id` on their watchlist.
+    pub fn watchers(&self, user_id: &str) -> Vec<String> {
+        self.watchers
+            .read()
+            .unwrap()
+            .get(user_id)
+            .map(|users| users.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn user_sockets(&self, user_id: &str) -> HashMap<usize, R> {
+        if let Some(ids) = self.users.read().unwrap().get(user_id) {
+            let sockets = self.sockets.read().unwrap();
+
+            ids.iter()
+                .filter_map(|id| sockets.get(id).map(|socket| (*id, socket.clone())))
+                .collect()
+        } else {
+            HashMap::default()
+        }
     }
 
     pub fn channel_sockets(&self, channel: &Channel) -> HashMap<usize, R> {
@@ -138,10 +375,24 @@ where R: Clone
     fn remove_presence_data(&self, id: usize, ch: &Channel) {
         let mut cpd = self.channel_presence_data.write().unwrap();
 
-        let should_remove = if let Some(members) = cpd.get_mut(&ch) {
-            members.remove(&id);
+        let should_remove = if let Some(presence) = cpd.get_mut(ch) {
+            if let Some(user_id) = presence.users.remove(&id) {
+                let last_socket = presence
+                    .members
+                    .get_mut(&user_id)
+                    .map(|member| {
+                        member.sockets.remove(&id);
+
+                        member.sockets.is_empty()
+                    })
+                    .unwrap_or(false);
+
+                if last_socket {
+                    presence.members.remove(&user_id);
+                }
+            }
 
-            members.is_empty()
+            presence.members.is_empty()
         } else {
             false
         };
@@ -158,31 +409,28 @@ where R: Clone
     ) -> Option<PusherMessageChannelData> {
         let presence_data = self.channel_presence_data.read().unwrap();
 
-        if let Some(members) = presence_data.get(&channel) {
-            if let Some(member) = members.get(&id) {
-                member.clone()
-            } else {
-                None
-            }
+        if let Some(presence) = presence_data.get(channel) {
+            presence
+                .users
+                .get(&id)
+                .and_then(|user_id| presence.members.get(user_id))
+                .map(|member| member.data.clone())
         } else {
             None
         }
     }
 
-    pub fn channel_members(&self, channel: &Channel) -> HashMap<usize, PusherMessageChannelData> {
-        if let Some(presence_data) = self.channel_presence_data.read().unwrap().get(channel) {
-            let hs: HashMap<usize, Option<PusherMessageChannelData>> = self
-                .channel_sockets(channel)
-                .iter()
-                .map(|(id, _)| (*id, presence_data.get(id).unwrap().clone()))
-                .collect();
-
-            hs.iter()
-                .filter(|(_, ws)| ws.is_some())
-                .map(|(s, ws)| (*s, ws.clone().unwrap()))
+    /// One entry per user subscribed to the presence channel, however many
+    /// sockets they are connected with.
+    pub fn channel_members(&self, channel: &Channel) -> Vec<PusherMessageChannelData> {
+        if let Some(presence) = self.channel_presence_data.read().unwrap().get(channel) {
+            presence
+                .members
+                .values()
+                .map(|member| member.data.clone())
                 .collect()
         } else {
-            HashMap::default()
+            Vec::new()
         }
     }
 }
@@ -190,6 +438,7 @@ where R: Clone
 #[cfg(test)]
 mod tests {
     use serde_json::Value;
+    use std::time::Duration;
     use crate::kind::Channel;
     use crate::messages::PusherMessageChannelData;
     use crate::namespace::Namespace;
@@ -230,8 +479,9 @@ mod tests {
 
         let ch = Channel::Public("test".to_string());
 
-        ns.add_to_channel(1, &ch, None);
-        ns.add_to_channel(2, &ch, None);
+        assert_eq!(Some(1), ns.add_to_channel(1, &ch, None));
+        assert_eq!(Some(2), ns.add_to_channel(2, &ch, None));
+        assert_eq!(None, ns.add_to_channel(2, &ch, None));
 
         let members = ns.channel_sockets(&ch);
 
@@ -248,8 +498,9 @@ mod tests {
         ns.add_to_channel(1, &ch, None);
 
         assert_eq!(1, ns.member_count_by_channel(&ch));
-        ns.remove_from_channel(1, &ch);
+        assert_eq!(Some(0), ns.remove_from_channel(1, &ch));
         assert_eq!(0, ns.member_count_by_channel(&ch));
+        assert_eq!(None, ns.remove_from_channel(1, &ch));
     }
 
     #[test]
@@ -275,9 +526,137 @@ mod tests {
         assert!(ns.get_presence_data(1, &ch).is_none());
         assert!(ns.get_presence_data(2, &ch).is_some());
 
+        assert!(ns.has_user(&ch, "s2"));
+        assert!(!ns.has_user(&ch, "s1"));
+
         ns.remove_from_channel(2, &ch);
         println!("{:?}", ns.channel_presence_data);
         assert!(ns.get_presence_data(2, &ch).is_none());
         assert!(ns.channel_presence_data.read().unwrap().is_empty())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn indexes_sockets_by_user() {
+        let ns: Namespace<&'static str> = Namespace::default();
+
+        ns.add_socket(1, "s1");
+        ns.add_socket(2, "s2");
+
+        assert!(ns.sign_in(1, "u1"));
+        assert!(!ns.sign_in(2, "u1"));
+
+        assert_eq!(2, ns.user_sockets("u1").len());
+
+        assert_eq!(Some("u1".to_string()), ns.sign_out(1));
+        assert_eq!(None, ns.sign_out(1));
+        assert_eq!(1, ns.user_sockets("u1").len());
+
+        assert!(ns.sign_in(2, "u2"));
+        assert!(ns.user_sockets("u1").is_empty());
+        assert!(ns.users.read().unwrap().get("u1").is_none());
+
+        assert!(!ns.sign_in(2, "u2"));
+        assert_eq!(Some("u2".to_string()), ns.user_of(2));
+        assert_eq!(1, ns.user_sockets("u2").len());
+    }
+
+    #[test]
+    fn indexes_watchlists() {
+        let ns: Namespace<&'static str> = Namespace::default();
+
+        ns.sign_in(1, "u1");
+        ns.set_watchlist("u1", &["u2".to_string(), "u3".to_string()]);
+        ns.set_watchlist("u4", &["u2".to_string()]);
+
+        let mut watchers = ns.watchers("u2");
+        watchers.sort();
+
+        assert_eq!(vec!["u1".to_string(), "u4".to_string()], watchers);
+        assert!(ns.is_online("u1"));
+        assert!(!ns.is_online("u2"));
+
+        ns.set_watchlist("u1", &["u3".to_string()]);
+
+        assert_eq!(vec!["u4".to_string()], ns.watchers("u2"));
+
+        ns.remove_watchlist("u1");
+        ns.remove_watchlist("u4");
+
+        assert!(ns.watchers("u2").is_empty());
+        assert!(ns.watchers("u3").is_empty());
+        assert!(ns.watchers.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn cached_events_expire() {
+        let ns: Namespace<&'static str> = Namespace::default();
+
+        let ch = Channel::Cache("cache-test".to_string());
+
+        assert!(ns.cached_event(&ch, Duration::from_secs(60)).is_none());
+
+        ns.cache_event(&ch, "first".to_string(), "{}".to_string());
+        ns.cache_event(&ch, "second".to_string(), "{}".to_string());
+
+        let cached = ns.cached_event(&ch, Duration::from_secs(60)).unwrap();
+        assert_eq!("second", cached.event);
+
+        assert!(ns.cached_event(&ch, Duration::from_secs(0)).is_none());
+        assert!(ns.cached_events.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn presence_members_are_keyed_by_user_id() {
+        let ns: Namespace<&'static str> = Namespace::default();
+
+        let ch = Channel::Presence("presence-test".to_string());
+
+        let member = |user_id: &str| PusherMessageChannelData {
+            user_info: Value::default(),
+            user_id: user_id.to_string(),
+        };
+
+        ns.add_to_channel(1, &ch, Some(member("u1")));
+        ns.add_to_channel(2, &ch, Some(member("u1")));
+        ns.add_to_channel(3, &ch, Some(member("u2")));
+
+        assert_eq!(3, ns.member_count_by_channel(&ch));
+        assert_eq!(2, ns.users_per_channel(&ch));
+        assert_eq!(2, ns.channel_members(&ch).len());
+        assert_eq!("u1", ns.get_presence_data(2, &ch).unwrap().user_id);
+
+        ns.remove_from_channel(1, &ch);
+
+        assert!(ns.has_user(&ch, "u1"));
+        assert_eq!(2, ns.users_per_channel(&ch));
+
+        ns.remove_from_channel(2, &ch);
+
+        assert!(!ns.has_user(&ch, "u1"));
+        assert_eq!(1, ns.users_per_channel(&ch));
+        assert_eq!("u2", ns.channel_members(&ch)[0].user_id);
+    }
+
+    #[test]
+    fn resubscribing_as_another_user_leaves_no_member_behind() {
+        let ns: Namespace<&'static str> = Namespace::default();
+
+        let ch = Channel::Presence("presence-test".to_string());
+
+        let member = |user_id: &str| PusherMessageChannelData {
+            user_info: Value::default(),
+            user_id: user_id.to_string(),
+        };
+
+        ns.add_to_channel(1, &ch, Some(member("u1")));
+        ns.add_to_channel(1, &ch, Some(member("u2")));
+
+        assert!(!ns.has_user(&ch, "u1"));
+        assert_eq!(1, ns.users_per_channel(&ch));
+        assert_eq!("u2", ns.channel_members(&ch)[0].user_id);
+
+        ns.remove_from_channel(1, &ch);
+
+        assert_eq!(0, ns.users_per_channel(&ch));
+    }
+}
@@ -1,5 +1,6 @@
 pub mod sqlite;
-use crate::app::App;
+use crate::app::{App, AppKey};
+use crate::webhooks::{DeadLetter, QueuedWebhook};
 use std::collections::HashMap;
 
 pub trait AppRepo: Send + Sync {
@@ -7,6 +8,50 @@ pub trait AppRepo: Send + Sync {
     fn find_by_id(&self, id: i64) -> Option<App>;
     fn find_by_key(&self, key: &String) -> Option<App>;
     fn insert_app(&mut self, app: &App) -> Result<(), Box<dyn std::error::Error>>;
+    fn update_app(&mut self, app: &App) -> Result<(), Box<dyn std::error::Error>>;
+    fn delete_app(&mut self, id: i64) -> Result<(), Box<dyn std::error::Error>>;
+    fn add_key(&mut self, app_id: i64, key: &AppKey) -> Result<(), Box<dyn std::error::Error>>;
+    /// Stores the app's new key pair together with the `retired` one it
+    /// replaced, either both or neither.
+    fn rotate_key(&mut self, app: &App, retired: &AppKey)
+        -> Result<(), Box<dyn std::error::Error>>;
+    /// Marks one of the app's additional keys as expiring at `expires_at`.
+    fn retire_key(
+        &mut self,
+        app_id: i64,
+        key: &str,
+        expires_at: i64,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Durable storage for webhooks that have not been delivered yet, and for the
+/// ones that were given up on.
+pub trait WebhookRepo: Send + Sync {
+    fn enqueue(
+        &mut self,
+        app_id: i64,
+        body: &str,
+        now: i64,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    /// Queued webhooks whose next attempt is due at `now`, oldest first.
+    fn due(&self, now: i64, limit: i64) -> Vec<QueuedWebhook>;
+    fn delivered(&mut self, id: i32) -> Result<(), Box<dyn std::error::Error>>;
+    fn retry(
+        &mut self,
+        id: i32,
+        attempts: i32,
+        next_attempt_at: i64,
+        error: &str,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    /// Moves a queued webhook to the dead-letter table.
+    fn dead_letter(
+        &mut self,
+        id: i32,
+        attempts: i32,
+        error: &str,
+        now: i64,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    fn dead_letters(&self, app_id: i64) -> Vec<DeadLetter>;
 }
 
 #[derive(Default, Debug)]
@@ -17,7 +62,7 @@ pub struct InMemoryAppRepo {
 
 impl AppRepo for InMemoryAppRepo {
     fn all(&self) -> Vec<App> {
-        todo!();
+        self.apps.values().cloned().collect()
     }
 
     fn find_by_id(&self, id: i64) -> Option<App> {
@@ -38,4 +83,162 @@ impl AppRepo for InMemoryAppRepo {
 
         Ok(())
     }
+
+    fn update_app(&mut self, app: &App) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(previous) = self.apps.insert(app.id, app.clone()) {
+            self.key_to_id.remove(&previous.key);
+        }
+
+        self.key_to_id.insert(app.key.clone(), app.id);
+
+        Ok(())
+    }
+
+    fn delete_app(&mut self, id: i64) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(app) = self.apps.remove(&id) {
+            self.key_to_id.remove(&app.key);
+
+            for key in app.keys {
+                self.key_to_id.remove(&key.key);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_key(&mut self, app_id: i64, key: &AppKey) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(app) = self.apps.get_mut(&app_id) {
+            if !app.keys.iter().any(|k| k.key == key.key) {
+                app.keys.push(key.clone());
+            }
+
+            self.key_to_id.insert(key.key.clone(), app_id);
+        }
+
+        Ok(())
+    }
+
+    fn rotate_key(
+        &mut self,
+        app: &App,
+        retired: &AppKey,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update_app(app)?;
+        self.add_key(app.id, retired)
+    }
+
+    fn retire_key(
+        &mut self,
+        app_id: i64,
+        key: &str,
+        expires_at: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(app) = self.apps.get_mut(&app_id) {
+            if let Some(app_key) = app.keys.iter_mut().find(|k| k.key == key) {
+                app_key.expires_at = Some(expires_at);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[derive(Default, Debug)]
+pub struct InMemoryWebhookRepo {
+    next_id: i32,
+    /// Queued webhooks along with the time their next attempt is due.
+    outbox: Vec<(i64, QueuedWebhook)>,
+    dead_letters: Vec<DeadLetter>,
+}
+
+#[cfg(test)]
+impl WebhookRepo for InMemoryWebhookRepo {
+    fn enqueue(
+        &mut self,
+        app_id: i64,
+        body: &str,
+        now: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.next_id += 1;
+
+        self.outbox.push((
+            now,
+            QueuedWebhook {
+                id: self.next_id,
+                app_id,
+                body: body.to_string(),
+                attempts: 0,
+            },
+        ));
+
+        Ok(())
+    }
+
+    fn due(&self, now: i64, limit: i64) -> Vec<QueuedWebhook> {
+        let mut due: Vec<&(i64, QueuedWebhook)> = self
+            .outbox
+            .iter()
+            .filter(|(next_attempt_at, _)| *next_attempt_at <= now)
+            .collect();
+
+        due.sort_by_key(|(next_attempt_at, _)| *next_attempt_at);
+
+        due.into_iter()
+            .take(limit as usize)
+            .map(|(_, webhook)| webhook.clone())
+            .collect()
+    }
+
+    fn delivered(&mut self, id: i32) -> Result<(), Box<dyn std::error::Error>> {
+        self.outbox.retain(|(_, webhook)| webhook.id != id);
+
+        Ok(())
+    }
+
+    fn retry(
+        &mut self,
+        id: i32,
+        attempts: i32,
+        next_attempt_at: i64,
+        _error: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(queued) = self.outbox.iter_mut().find(|(_, webhook)| webhook.id == id) {
+            queued.0 = next_attempt_at;
+            queued.1.attempts = attempts;
+        }
+
+        Ok(())
+    }
+
+    fn dead_letter(
+        &mut self,
+        id: i32,
+        attempts: i32,
+        error: &str,
+        now: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(index) = self.outbox.iter().position(|(_, webhook)| webhook.id == id) {
+            let (_, webhook) = self.outbox.remove(index);
+
+            self.dead_letters.push(DeadLetter {
+                id: webhook.id,
+                app_id: webhook.app_id,
+                body: webhook.body,
+                attempts,
+                last_error: Some(error.to_string()),
+                failed_at: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn dead_letters(&self, app_id: i64) -> Vec<DeadLetter> {
+        self.dead_letters
+            .iter()
+            .filter(|dead_letter| dead_letter.app_id == app_id)
+            .cloned()
+            .collect()
+    }
 }
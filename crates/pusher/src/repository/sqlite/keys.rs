@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+
+use super::schema::app_keys;
+use crate::app::AppKey;
+
+#[derive(Debug, Insertable)]
+#[table_name = "app_keys"]
+pub(super) struct NewAppKey<'a> {
+    pub app_id: i64,
+    pub key: &'a str,
+    pub secret: &'a str,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Queryable)]
+struct QueryAppKey {
+    pub _id: i32,
+    pub app_id: i64,
+    pub key: String,
+    pub secret: String,
+    pub expires_at: Option<i64>,
+}
+
+impl From<QueryAppKey> for AppKey {
+    fn from(key: QueryAppKey) -> Self {
+        AppKey {
+            key: key.key,
+            secret: key.secret,
+            expires_at: key.expires_at,
+        }
+    }
+}
+
+/// Key pairs of the given apps, grouped by app id.
+pub(super) fn load_keys(conn: &SqliteConnection, app_ids: &[i64]) -> HashMap<i64, Vec<AppKey>> {
+    use super::schema::app_keys::dsl;
+
+    let rows = dsl::app_keys
+        .filter(dsl::app_id.eq_any(app_ids))
+        .order(dsl::id.asc())
+        .load::<QueryAppKey>(conn)
+        .unwrap_or_default();
+
+    let mut keys: HashMap<i64, Vec<AppKey>> = HashMap::new();
+
+    for row in rows {
+        keys.entry(row.app_id).or_default().push(row.into());
+    }
+
+    keys
+}
@@ -1,6 +1,8 @@
+mod keys;
 pub mod schema;
+mod webhooks;
 
-use crate::app::App;
+use crate::app::{App, AppKey};
 use crate::AppRepo;
 use diesel::prelude::*;
 use diesel::SqliteConnection;
@@ -8,13 +10,27 @@ use std::sync::Mutex;
 
 use schema::apps;
 
-#[derive(Debug, Insertable)]
+#[derive(Debug, Insertable, AsChangeset)]
 #[table_name = "apps"]
+#[changeset_options(treat_none_as_null = "true")]
 struct NewApp<'a> {
     pub id: i64,
     pub name: &'a str,
     pub key: &'a str,
     pub secret: &'a str,
+    pub enable_client_events: bool,
+    pub webhook_url: Option<&'a str>,
+    pub enable_client_event_webhooks: bool,
+    pub max_presence_members: i32,
+    pub max_presence_user_info_size: i32,
+    pub encryption_master_key: Option<&'a str>,
+    pub enable_watchlist_webhooks: bool,
+    pub enable_subscription_count: bool,
+    pub enabled: bool,
+    pub max_connections: Option<i32>,
+    pub max_message_size: i32,
+    pub webhook_events: String,
+    pub allowed_origins: String,
 }
 
 #[derive(Debug, Queryable)]
@@ -23,16 +39,24 @@ struct QueryApp {
     pub name: String,
     pub key: String,
     pub secret: String,
+    pub enable_client_events: bool,
+    pub webhook_url: Option<String>,
+    pub enable_client_event_webhooks: bool,
+    pub max_presence_members: i32,
+    pub max_presence_user_info_size: i32,
+    pub encryption_master_key: Option<String>,
+    pub enable_watchlist_webhooks: bool,
+    pub enable_subscription_count: bool,
+    pub enabled: bool,
+    pub max_connections: Option<i32>,
+    pub max_message_size: i32,
+    pub webhook_events: String,
+    pub allowed_origins: String,
 }
 
 impl Into<App> for QueryApp {
     fn into(self) -> App {
-        App {
-            id: self.id,
-            name: self.name,
-            key: self.key,
-            secret: self.secret,
-        }
+        (&self).into()
     }
 }
 
@@ -43,6 +67,52 @@ impl Into<App> for &QueryApp {
             name: self.name.clone(),
             key: self.key.clone(),
             secret: self.secret.clone(),
+            enable_client_events: self.enable_client_events,
+            webhook_url: self.webhook_url.clone(),
+            enable_client_event_webhooks: self.enable_client_event_webhooks,
+            max_presence_members: self.max_presence_members,
+            max_presence_user_info_size: self.max_presence_user_info_size,
+            encryption_master_key: self.encryption_master_key.clone(),
+            enable_watchlist_webhooks: self.enable_watchlist_webhooks,
+            enable_subscription_count: self.enable_subscription_count,
+            enabled: self.enabled,
+            max_connections: self.max_connections,
+            max_message_size: self.max_message_size,
+            webhook_events: split_list(&self.webhook_events),
+            allowed_origins: split_list(&self.allowed_origins),
+            keys: Vec::new(),
+        }
+    }
+}
+
+/// Lists are stored comma separated.
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+impl<'a> From<&'a App> for NewApp<'a> {
+    fn from(app: &'a App) -> Self {
+        NewApp {
+            id: app.id,
+            name: app.name.as_str(),
+            key: app.key.as_str(),
+            secret: app.secret.as_str(),
+            enable_client_events: app.enable_client_events,
+            webhook_url: app.webhook_url.as_deref(),
+            enable_client_event_webhooks: app.enable_client_event_webhooks,
+            max_presence_members: app.max_presence_members,
+            max_presence_user_info_size: app.max_presence_user_info_size,
+            encryption_master_key: app.encryption_master_key.as_deref(),
+            enable_watchlist_webhooks: app.enable_watchlist_webhooks,
+            enable_subscription_count: app.enable_subscription_count,
+            enabled: app.enabled,
+            max_connections: app.max_connections,
+            max_message_size: app.max_message_size,
+            webhook_events: app.webhook_events.join(","),
+            allowed_origins: app.allowed_origins.join(","),
         }
     }
 }
@@ -63,11 +133,22 @@ impl AppRepo for SqliteRepo {
     fn all(&self) -> Vec<App> {
         use schema::apps::dsl;
 
-        let result: Vec<QueryApp> = dsl::apps
-            .get_results::<QueryApp>(&*self.conn.lock().unwrap())
-            .unwrap();
+        let conn = self.conn.lock().unwrap();
+
+        let result: Vec<QueryApp> = dsl::apps.get_results::<QueryApp>(&*conn).unwrap();
+
+        let ids: Vec<i64> = result.iter().map(|app| app.id).collect();
 
-        result.iter().map(|app| app.into()).collect()
+        let mut keys = keys::load_keys(&conn, &ids);
+
+        result
+            .into_iter()
+            .map(|query_app| {
+                let mut app: App = query_app.into();
+                app.keys = keys.remove(&app.id).unwrap_or_default();
+                app
+            })
+            .collect()
     }
 
     fn find_by_id(&self, id: i64) -> Option<App> {
@@ -78,35 +159,42 @@ impl AppRepo for SqliteRepo {
         let result: QueryResult<QueryApp> = dsl::apps.filter(dsl::id.eq(id)).first(&*conn);
 
         if let Ok(query_app) = result {
-            Some(query_app.into())
+            let mut app: App = query_app.into();
+            app.keys = keys::load_keys(&conn, &[id])
+                .remove(&id)
+                .unwrap_or_default();
+
+            Some(app)
         } else {
             None
         }
     }
 
     fn find_by_key(&self, key: &String) -> Option<App> {
-        use schema::apps::dsl;
+        use schema::{app_keys, apps::dsl};
 
-        let conn = self.conn.lock().unwrap();
+        let id = {
+            let conn = self.conn.lock().unwrap();
 
-        let result = dsl::apps
-            .filter(dsl::key.eq(key.as_str()))
-            .first::<QueryApp>(&*conn);
+            dsl::apps
+                .filter(dsl::key.eq(key.as_str()))
+                .select(dsl::id)
+                .first::<i64>(&*conn)
+                .ok()
+                .or_else(|| {
+                    app_keys::table
+                        .filter(app_keys::key.eq(key.as_str()))
+                        .select(app_keys::app_id)
+                        .first::<i64>(&*conn)
+                        .ok()
+                })
+        };
 
-        if let Ok(query_app) = result {
-            Some(query_app.into())
-        } else {
-            None
-        }
+        id.and_then(|id| self.find_by_id(id))
     }
 
     fn insert_app(&mut self, app: &App) -> Result<(), Box<dyn std::error::Error>> {
-        let new_app = NewApp {
-            id: app.id,
-            name: app.name.as_str(),
-            key: app.key.as_str(),
-            secret: app.key.as_str(),
-        };
+        let new_app = NewApp::from(app);
 
         diesel::insert_into(apps::table)
             .values(&new_app)
@@ -114,4 +202,168 @@ impl AppRepo for SqliteRepo {
 
         Ok(())
     }
+
+    fn update_app(&mut self, app: &App) -> Result<(), Box<dyn std::error::Error>> {
+        use schema::apps::dsl;
+
+        diesel::update(dsl::apps.filter(dsl::id.eq(app.id)))
+            .set(&NewApp::from(app))
+            .execute(&*self.conn.lock().unwrap())?;
+
+        Ok(())
+    }
+
+    fn delete_app(&mut self, id: i64) -> Result<(), Box<dyn std::error::Error>> {
+        use schema::{app_keys, apps::dsl};
+
+        let conn = self.conn.lock().unwrap();
+
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            diesel::delete(app_keys::table.filter(app_keys::app_id.eq(id))).execute(&*conn)?;
+            diesel::delete(dsl::apps.filter(dsl::id.eq(id))).execute(&*conn)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    fn add_key(&mut self, app_id: i64, key: &AppKey) -> Result<(), Box<dyn std::error::Error>> {
+        let new_key = keys::NewAppKey {
+            app_id,
+            key: key.key.as_str(),
+            secret: key.secret.as_str(),
+            expires_at: key.expires_at,
+        };
+
+        diesel::insert_into(schema::app_keys::table)
+            .values(&new_key)
+            .execute(&*self.conn.lock().unwrap())?;
+
+        Ok(())
+    }
+
+    fn rotate_key(
+        &mut self,
+        app: &App,
+        retired: &AppKey,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use schema::apps::dsl;
+
+        let conn = self.conn.lock().unwrap();
+
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            diesel::update(dsl::apps.filter(dsl::id.eq(app.id)))
+                .set(&NewApp::from(app))
+                .execute(&*conn)?;
+
+            diesel::insert_into(schema::app_keys::table)
+                .values(&keys::NewAppKey {
+                    app_id: app.id,
+                    key: retired.key.as_str(),
+                    secret: retired.secret.as_str(),
+                    expires_at: retired.expires_at,
+                })
+                .execute(&*conn)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    fn retire_key(
+        &mut self,
+        app_id: i64,
+        key: &str,
+        expires_at: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use schema::app_keys::dsl;
+
+        diesel::update(dsl::app_keys.filter(dsl::app_id.eq(app_id).and(dsl::key.eq(key))))
+            .set(dsl::expires_at.eq(Some(expires_at)))
+            .execute(&*self.conn.lock().unwrap())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::connection::SimpleConnection;
+
+    fn repo() -> SqliteRepo {
+        let conn = SqliteConnection::establish(":memory:").unwrap();
+
+        for migration in [
+            include_str!("../../../migrations/2022-01-31-215955_create_apps/up.sql"),
+            include_str!("../../../migrations/2022-02-20-120000_add_enable_client_events_to_apps/up.sql"),
+            include_str!("../../../migrations/2022-02-27-120000_add_webhook_url_to_apps/up.sql"),
+            include_str!("../../../migrations/2022-03-06-120000_add_enable_client_event_webhooks_to_apps/up.sql"),
+            include_str!("../../../migrations/2022-03-20-120000_add_presence_limits_to_apps/up.sql"),
+            include_str!("../../../migrations/2022-03-27-120000_add_encryption_master_key_to_apps/up.sql"),
+            include_str!("../../../migrations/2022-04-03-120000_add_enable_watchlist_webhooks_to_apps/up.sql"),
+            include_str!("../../../migrations/2022-04-10-120000_add_enable_subscription_count_to_apps/up.sql"),
+            include_str!("../../../migrations/2022-04-17-120000_add_settings_to_apps/up.sql"),
+            include_str!("../../../migrations/2022-04-24-120000_create_app_keys/up.sql"),
+        ] {
+            conn.batch_execute(migration).unwrap();
+        }
+
+        SqliteRepo::new(conn)
+    }
+
+    #[test]
+    fn updates_app_settings() {
+        let mut repo = repo();
+
+        let mut app = App::new("test".to_string());
+        app.webhook_url = Some("http://localhost/webhooks".to_string());
+
+        repo.insert_app(&app).unwrap();
+
+        app.enabled = false;
+        app.max_connections = Some(500);
+        app.webhook_url = None;
+        app.webhook_events = vec!["channel_occupied".to_string(), "member_added".to_string()];
+        app.allowed_origins = vec!["https://example.com".to_string()];
+
+        repo.update_app(&app).unwrap();
+
+        let found = repo.find_by_key(&app.key).unwrap();
+
+        assert!(!found.enabled);
+        assert_eq!(Some(500), found.max_connections);
+        assert_eq!(None, found.webhook_url);
+        assert_eq!(app.webhook_events, found.webhook_events);
+        assert_eq!(app.allowed_origins, found.allowed_origins);
+        assert_eq!(1, repo.all().len());
+
+        let key = AppKey::generate();
+
+        repo.add_key(app.id, &key).unwrap();
+        repo.retire_key(app.id, &key.key, 1000).unwrap();
+
+        let found = repo.find_by_key(&key.key).unwrap();
+
+        assert_eq!(app.id, found.id);
+        assert_eq!(key.key, found.keys[0].key);
+        assert_eq!(Some(1000), found.keys[0].expires_at);
+
+        let mut app = found;
+        let retired = app.rotate_key(2000);
+
+        repo.rotate_key(&app, &retired).unwrap();
+
+        let found = repo.find_by_key(&retired.key).unwrap();
+
+        assert_eq!(app.key, found.key);
+        assert_eq!(Some(2000), found.keys[1].expires_at);
+
+        repo.delete_app(app.id).unwrap();
+
+        assert!(repo.find_by_id(app.id).is_none());
+        assert!(repo.all().is_empty());
+    }
 }
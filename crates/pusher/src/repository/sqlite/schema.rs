@@ -4,5 +4,50 @@ table! {
         name -> Text,
         key -> Text,
         secret -> Text,
+        enable_client_events -> Bool,
+        webhook_url -> Nullable<Text>,
+        enable_client_event_webhooks -> Bool,
+        max_presence_members -> Integer,
+        max_presence_user_info_size -> Integer,
+        encryption_master_key -> Nullable<Text>,
+        enable_watchlist_webhooks -> Bool,
+        enable_subscription_count -> Bool,
+        enabled -> Bool,
+        max_connections -> Nullable<Integer>,
+        max_message_size -> Integer,
+        webhook_events -> Text,
+        allowed_origins -> Text,
+    }
+}
+
+table! {
+    webhook_outbox (id) {
+        id -> Integer,
+        app_id -> BigInt,
+        body -> Text,
+        attempts -> Integer,
+        next_attempt_at -> BigInt,
+        last_error -> Nullable<Text>,
+    }
+}
+
+table! {
+    webhook_dead_letters (id) {
+        id -> Integer,
+        app_id -> BigInt,
+        body -> Text,
+        attempts -> Integer,
+        last_error -> Nullable<Text>,
+        failed_at -> BigInt,
+    }
+}
+
+table! {
+    app_keys (id) {
+        id -> Integer,
+        app_id -> BigInt,
+        key -> Text,
+        secret -> Text,
+        expires_at -> Nullable<BigInt>,
     }
 }
@@ -0,0 +1,230 @@
+use diesel::prelude::*;
+
+use super::schema::{webhook_dead_letters, webhook_outbox};
+use super::SqliteRepo;
+use crate::repository::WebhookRepo;
+use crate::webhooks::{DeadLetter, QueuedWebhook};
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhook_outbox"]
+struct NewQueuedWebhook<'a> {
+    pub app_id: i64,
+    pub body: &'a str,
+    pub attempts: i32,
+    pub next_attempt_at: i64,
+}
+
+#[derive(Debug, Queryable)]
+struct QueryQueuedWebhook {
+    pub id: i32,
+    pub app_id: i64,
+    pub body: String,
+    pub attempts: i32,
+}
+
+impl From<QueryQueuedWebhook> for QueuedWebhook {
+    fn from(webhook: QueryQueuedWebhook) -> Self {
+        QueuedWebhook {
+            id: webhook.id,
+            app_id: webhook.app_id,
+            body: webhook.body,
+            attempts: webhook.attempts,
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhook_dead_letters"]
+struct NewDeadLetter<'a> {
+    pub app_id: i64,
+    pub body: &'a str,
+    pub attempts: i32,
+    pub last_error: Option<&'a str>,
+    pub failed_at: i64,
+}
+
+#[derive(Debug, Queryable)]
+struct QueryDeadLetter {
+    pub id: i32,
+    pub app_id: i64,
+    pub body: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub failed_at: i64,
+}
+
+impl From<QueryDeadLetter> for DeadLetter {
+    fn from(dead_letter: QueryDeadLetter) -> Self {
+        DeadLetter {
+            id: dead_letter.id,
+            app_id: dead_letter.app_id,
+            body: dead_letter.body,
+            attempts: dead_letter.attempts,
+            last_error: dead_letter.last_error,
+            failed_at: dead_letter.failed_at,
+        }
+    }
+}
+
+impl WebhookRepo for SqliteRepo {
+    fn enqueue(
+        &mut self,
+        app_id: i64,
+        body: &str,
+        now: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let webhook = NewQueuedWebhook {
+            app_id,
+            body,
+            attempts: 0,
+            next_attempt_at: now,
+        };
+
+        diesel::insert_into(webhook_outbox::table)
+            .values(&webhook)
+            .execute(&*self.conn.lock().unwrap())?;
+
+        Ok(())
+    }
+
+    fn due(&self, now: i64, limit: i64) -> Vec<QueuedWebhook> {
+        let conn = self.conn.lock().unwrap();
+
+        webhook_outbox::table
+            .filter(webhook_outbox::next_attempt_at.le(now))
+            .order(webhook_outbox::next_attempt_at.asc())
+            .limit(limit)
+            .select((
+                webhook_outbox::id,
+                webhook_outbox::app_id,
+                webhook_outbox::body,
+                webhook_outbox::attempts,
+            ))
+            .load::<QueryQueuedWebhook>(&*conn)
+            .map(|rows| rows.into_iter().map(QueuedWebhook::from).collect())
+            .unwrap_or_default()
+    }
+
+    fn delivered(&mut self, id: i32) -> Result<(), Box<dyn std::error::Error>> {
+        diesel::delete(webhook_outbox::table.filter(webhook_outbox::id.eq(id)))
+            .execute(&*self.conn.lock().unwrap())?;
+
+        Ok(())
+    }
+
+    fn retry(
+        &mut self,
+        id: i32,
+        attempts: i32,
+        next_attempt_at: i64,
+        error: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        diesel::update(webhook_outbox::table.filter(webhook_outbox::id.eq(id)))
+            .set((
+                webhook_outbox::attempts.eq(attempts),
+                webhook_outbox::next_attempt_at.eq(next_attempt_at),
+                webhook_outbox::last_error.eq(Some(error)),
+            ))
+            .execute(&*self.conn.lock().unwrap())?;
+
+        Ok(())
+    }
+
+    fn dead_letter(
+        &mut self,
+        id: i32,
+        attempts: i32,
+        error: &str,
+        now: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            let (app_id, body) = webhook_outbox::table
+                .filter(webhook_outbox::id.eq(id))
+                .select((webhook_outbox::app_id, webhook_outbox::body))
+                .first::<(i64, String)>(&*conn)?;
+
+            diesel::insert_into(webhook_dead_letters::table)
+                .values(&NewDeadLetter {
+                    app_id,
+                    body: &body,
+                    attempts,
+                    last_error: Some(error),
+                    failed_at: now,
+                })
+                .execute(&*conn)?;
+
+            diesel::delete(webhook_outbox::table.filter(webhook_outbox::id.eq(id)))
+                .execute(&*conn)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    fn dead_letters(&self, app_id: i64) -> Vec<DeadLetter> {
+        let conn = self.conn.lock().unwrap();
+
+        webhook_dead_letters::table
+            .filter(webhook_dead_letters::app_id.eq(app_id))
+            .order(webhook_dead_letters::id.desc())
+            .load::<QueryDeadLetter>(&*conn)
+            .map(|rows| rows.into_iter().map(DeadLetter::from).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::connection::SimpleConnection;
+    use diesel::SqliteConnection;
+
+    fn repo() -> SqliteRepo {
+        let conn = SqliteConnection::establish(":memory:").unwrap();
+
+        conn.batch_execute(include_str!(
+            "../../../migrations/2022-03-13-120000_create_webhook_outbox/up.sql"
+        ))
+        .unwrap();
+
+        SqliteRepo::new(conn)
+    }
+
+    #[test]
+    fn moves_webhooks_through_the_outbox() {
+        let mut repo = repo();
+
+        repo.enqueue(1, "{\"events\":[]}", 100).unwrap();
+        repo.enqueue(2, "{\"events\":[]}", 200).unwrap();
+
+        assert!(repo.due(99, 10).is_empty());
+
+        let due = repo.due(200, 10);
+        assert_eq!(2, due.len());
+        assert_eq!(1, due[0].app_id);
+
+        repo.retry(due[0].id, 1, 1000, "503").unwrap();
+
+        let due = repo.due(500, 10);
+        assert_eq!(1, due.len());
+        assert_eq!(2, due[0].app_id);
+
+        repo.delivered(due[0].id).unwrap();
+
+        let retried = repo.due(1000, 10).remove(0);
+        assert_eq!(1, retried.attempts);
+
+        repo.dead_letter(retried.id, 2, "504", 2000).unwrap();
+
+        assert!(repo.due(i64::MAX, 10).is_empty());
+
+        let dead_letters = repo.dead_letters(1);
+        assert_eq!(1, dead_letters.len());
+        assert_eq!(2, dead_letters[0].attempts);
+        assert_eq!(Some("504"), dead_letters[0].last_error.as_deref());
+        assert!(repo.dead_letters(2).is_empty());
+    }
+}
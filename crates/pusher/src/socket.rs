@@ -90,3 +90,18 @@ impl ToString for Socket {
         val
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::socket::Socket;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn parses_formatted_socket_ids() {
+        let socket = Socket { id: 123456789 };
+
+        assert_eq!("1234.56789", socket.to_string());
+        assert!(Socket::try_from(socket.to_string()) == Ok(socket));
+        assert!(Socket::try_from("not-a-socket".to_string()).is_err());
+    }
+}
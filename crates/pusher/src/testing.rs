@@ -0,0 +1,259 @@
+//! Shared setup for tests that go through the HTTP API handlers or the
+//! WebSocket handler.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use actix::{Actor, Addr, Context, Handler, Message, MessageResult};
+use actix_web::http::Method;
+use actix_web::test::TestRequest;
+use actix_web::web;
+use md5::{Digest, Md5};
+use parking_lot::Mutex;
+use serde_json::Value;
+
+use crate::adapter::{Adapter, InMemoryAdapter};
+use crate::app::App;
+use crate::auth::{now, sign, RequestPayload};
+use crate::config::{AdminScope, AdminToken, Config};
+use crate::kind::WebSocket;
+use crate::messages::{OutgoingMessage, PusherMessage};
+use crate::repository::{AppRepo, InMemoryAppRepo};
+use crate::socket::Socket;
+use crate::webhooks::Webhook;
+use crate::ws::errors::WsError;
+use crate::ws::{Connect, MessageWrapper, Terminate, WebSocketHandler};
+
+/// Bearer token granted the manage scope on every `TestServer`.
+pub const ADMIN_TOKEN: &str = "manager";
+
+/// The state the handlers extract, backed by in-memory repositories.
+pub struct TestServer {
+    pub adapter: Arc<dyn Adapter>,
+    pub repo: Arc<Mutex<dyn AppRepo>>,
+    pub handler: Addr<WebSocketHandler>,
+    pub config: Config,
+    /// Receives the webhooks the handler sends instead of a dispatcher.
+    pub webhooks: Addr<Recorder>,
+}
+
+impl TestServer {
+    /// Starts the actors with `apps` stored, which has to happen inside an
+    /// actix `System`.
+    pub fn start(apps: &[&App]) -> Self {
+        let mut repo = InMemoryAppRepo::default();
+
+        for app in apps {
+            repo.insert_app(app).unwrap();
+        }
+
+        let repo: Arc<Mutex<dyn AppRepo>> = Arc::new(Mutex::new(repo));
+
+        let adapter: Arc<dyn Adapter> = Arc::new(InMemoryAdapter::default());
+        let config = Config {
+            admin_tokens: vec![AdminToken {
+                token: ADMIN_TOKEN.to_string(),
+                scope: AdminScope::Manage,
+            }],
+            ..Config::default()
+        };
+
+        let webhooks = Recorder::default().start();
+
+        let handler = WebSocketHandler::new(
+            adapter.clone(),
+            repo.clone(),
+            webhooks.clone().recipient(),
+            config.clone(),
+        )
+        .start();
+
+        Self {
+            adapter,
+            repo,
+            handler,
+            config,
+            webhooks,
+        }
+    }
+
+    /// Connects a client to `app`, returning its socket and the recorder of
+    /// everything sent to it.
+    pub async fn connect(&self, app: &App) -> (WebSocket, Addr<Recorder>) {
+        let client = Recorder::default().start();
+
+        let ws = WebSocket {
+            id: 0,
+            conn: client.clone().recipient(),
+            presence_data: None,
+            channels: vec![],
+            app_id: app.id,
+        };
+
+        let id = self
+            .handler
+            .send(Connect {
+                ws: ws.clone(),
+                origin: None,
+                terminate: client.clone().recipient(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        (WebSocket { id, ..ws }, client)
+    }
+
+    /// Handles `message` as if the client had sent it as a frame.
+    pub async fn send(&self, ws: &WebSocket, message: Value) -> Result<(), Box<dyn WsError>> {
+        let message: PusherMessage = serde_json::from_value(message).unwrap();
+
+        self.handler
+            .send(MessageWrapper {
+                ws: ws.clone(),
+                message,
+            })
+            .await
+            .unwrap()
+    }
+
+    /// Subscribes the client to `channel`, signing the subscription with the
+    /// app's key pair.
+    pub async fn subscribe(
+        &self,
+        app: &App,
+        ws: &WebSocket,
+        channel: &str,
+        channel_data: Option<Value>,
+    ) {
+        let channel_data = channel_data.map(|data| data.to_string());
+
+        let mut signed = format!("{}:{}", Socket { id: ws.id }.to_string(), channel);
+
+        if let Some(channel_data) = &channel_data {
+            signed = format!("{}:{}", signed, channel_data);
+        }
+
+        let message = serde_json::json!({
+            "event": "pusher:subscribe",
+            "data": {
+                "channel": channel,
+                "auth": format!("{}:{}", app.key, sign(&app.secret, &signed)),
+                "channel_data": channel_data,
+            },
+        });
+
+        self.send(ws, message).await.unwrap();
+    }
+
+    /// Registers the shared state with an `actix_web::App` through `configure`.
+    pub fn data(&self) -> impl FnOnce(&mut web::ServiceConfig) {
+        let adapter = self.adapter.clone();
+        let repo = self.repo.clone();
+        let handler = self.handler.clone();
+        let config = self.config.clone();
+
+        move |cfg| {
+            cfg.data(adapter).data(repo).data(handler).data(config);
+        }
+    }
+}
+
+/// A Pusher HTTP API request to `path`, signed with the app's key pair.
+pub fn signed(
+    app: &App,
+    method: Method,
+    path: &str,
+    query: &[(&str, &str)],
+    body: &str,
+) -> TestRequest {
+    let mut params: BTreeMap<String, String> = query
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    params.insert("auth_key".to_string(), app.key.clone());
+    params.insert("auth_timestamp".to_string(), now().to_string());
+    params.insert("auth_version".to_string(), "1.0".to_string());
+
+    if !body.is_empty() {
+        params.insert(
+            "body_md5".to_string(),
+            hex::encode(Md5::digest(body.as_bytes())),
+        );
+    }
+
+    let payload = RequestPayload {
+        method: method.as_str(),
+        path,
+        params: &params,
+        body: body.as_bytes(),
+    };
+
+    let signature = sign(&app.secret, &payload.string_to_sign());
+
+    params.insert("auth_signature".to_string(), signature);
+
+    let query = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>()
+        .join("&");
+
+    TestRequest::with_uri(&format!("{}?{}", path, query))
+        .method(method)
+        .header("content-type", "application/json")
+        .set_payload(body.to_string())
+}
+
+/// Stands in for a client connection or the webhook dispatcher, keeping
+/// everything it is sent as JSON until it is taken.
+#[derive(Default)]
+pub struct Recorder {
+    received: Vec<Value>,
+}
+
+impl Actor for Recorder {
+    type Context = Context<Self>;
+}
+
+/// Returns what a `Recorder` received so far and clears it.
+#[derive(Message)]
+#[rtype(result = "Vec<Value>")]
+pub struct Take;
+
+impl Handler<OutgoingMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: OutgoingMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.received.push(serde_json::to_value(&msg.0).unwrap());
+    }
+}
+
+impl Handler<Terminate> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: Terminate, _ctx: &mut Self::Context) -> Self::Result {
+        let error = msg.error.to_msg().unwrap();
+
+        self.received.push(serde_json::from_str(&error).unwrap());
+    }
+}
+
+impl Handler<Webhook> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: Webhook, _ctx: &mut Self::Context) -> Self::Result {
+        for event in msg.events {
+            self.received.push(serde_json::to_value(&event).unwrap());
+        }
+    }
+}
+
+impl Handler<Take> for Recorder {
+    type Result = MessageResult<Take>;
+
+    fn handle(&mut self, _msg: Take, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(std::mem::take(&mut self.received))
+    }
+}
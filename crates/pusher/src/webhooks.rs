@@ -0,0 +1,503 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix::prelude::*;
+use actix_web::client::Client;
+use log::warn;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::auth::sign;
+use crate::config::Config;
+use crate::repository::WebhookRepo;
+use crate::AppRepo;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the outbox is checked for webhooks left over from earlier runs.
+const OUTBOX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Queued webhooks picked up per outbox check.
+const OUTBOX_POLL_LIMIT: i64 = 100;
+
+/// Events sent in one request before a batch is flushed early.
+const MAX_EVENTS_PER_WEBHOOK: usize = 100;
+
+/// Upper bound on the delay between two delivery attempts.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Names of the events an app can select in `webhook_events`.
+pub const WEBHOOK_EVENT_NAMES: [&str; 9] = [
+    "channel_occupied",
+    "channel_vacated",
+    "member_added",
+    "member_removed",
+    "cache_miss",
+    "subscription_count",
+    "user_online",
+    "user_offline",
+    "client_event",
+];
+
+/// An event reported to an app's webhook endpoint, see
+/// https://pusher.com/docs/channels/server_api/webhooks/
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    ChannelOccupied {
+        channel: String,
+    },
+    ChannelVacated {
+        channel: String,
+    },
+    MemberAdded {
+        channel: String,
+        user_id: String,
+    },
+    MemberRemoved {
+        channel: String,
+        user_id: String,
+    },
+    CacheMiss {
+        channel: String,
+    },
+    SubscriptionCount {
+        channel: String,
+        subscription_count: usize,
+    },
+    UserOnline {
+        user_id: String,
+    },
+    UserOffline {
+        user_id: String,
+    },
+    ClientEvent {
+        channel: String,
+        event: String,
+        data: String,
+        socket_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        user_id: Option<String>,
+    },
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::ChannelOccupied { .. } => "channel_occupied",
+            WebhookEvent::ChannelVacated { .. } => "channel_vacated",
+            WebhookEvent::MemberAdded { .. } => "member_added",
+            WebhookEvent::MemberRemoved { .. } => "member_removed",
+            WebhookEvent::CacheMiss { .. } => "cache_miss",
+            WebhookEvent::SubscriptionCount { .. } => "subscription_count",
+            WebhookEvent::UserOnline { .. } => "user_online",
+            WebhookEvent::UserOffline { .. } => "user_offline",
+            WebhookEvent::ClientEvent { .. } => "client_event",
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Webhook {
+    pub app_id: i64,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    time_ms: u128,
+    events: &'a [WebhookEvent],
+}
+
+/// A serialized webhook request waiting in the outbox.
+#[derive(Clone, Debug)]
+pub struct QueuedWebhook {
+    pub id: i32,
+    pub app_id: i64,
+    pub body: String,
+    pub attempts: i32,
+}
+
+/// A webhook request that exhausted its delivery attempts.
+#[derive(Clone, Debug, Serialize)]
+pub struct DeadLetter {
+    pub id: i32,
+    pub app_id: i64,
+    pub body: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub failed_at: i64,
+}
+
+/// Collects webhook events per app for a short window, writes each batch to
+/// the outbox and posts it to the app's configured URL, retrying with
+/// exponential backoff until it is delivered or dead-lettered.
+pub struct WebhookDispatcher {
+    repo: Arc<Mutex<dyn AppRepo>>,
+    outbox: Arc<Mutex<dyn WebhookRepo>>,
+    config: Config,
+    pending: HashMap<i64, Vec<WebhookEvent>>,
+    in_flight: HashSet<i32>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(
+        repo: Arc<Mutex<dyn AppRepo>>,
+        outbox: Arc<Mutex<dyn WebhookRepo>>,
+        config: Config,
+    ) -> Self {
+        Self {
+            repo,
+            outbox,
+            config,
+            pending: HashMap::new(),
+            in_flight: HashSet::new(),
+        }
+    }
+
+    /// Writes the events collected for `app_id` to the outbox as one request.
+    fn flush(&mut self, app_id: i64, ctx: &mut Context<Self>) {
+        let events = match self.pending.remove(&app_id) {
+            Some(events) if !events.is_empty() => events,
+            _ => return,
+        };
+
+        let body = serde_json::to_string(&WebhookPayload {
+            time_ms: now_ms(),
+            events: &events,
+        })
+        .unwrap();
+
+        if let Err(e) = self.outbox.lock().enqueue(app_id, &body, now_ms() as i64) {
+            warn!("could not queue webhook for app {}: {}", app_id, e);
+            return;
+        }
+
+        self.deliver_due(ctx);
+    }
+
+    fn deliver_due(&mut self, ctx: &mut Context<Self>) {
+        let due = self.outbox.lock().due(now_ms() as i64, OUTBOX_POLL_LIMIT);
+
+        for webhook in due {
+            if self.in_flight.insert(webhook.id) {
+                self.deliver(webhook, ctx);
+            }
+        }
+    }
+
+    fn deliver(&mut self, webhook: QueuedWebhook, ctx: &mut Context<Self>) {
+        let app = self.repo.lock().find_by_id(webhook.app_id);
+
+        let app = match app {
+            Some(app) => app,
+            None => {
+                self.failed(webhook, "app not found".to_string(), true, ctx);
+                return;
+            }
+        };
+
+        let url = match app.webhook_url.clone() {
+            Some(url) => url,
+            None => {
+                self.failed(webhook, "webhook url not configured".to_string(), true, ctx);
+                return;
+            }
+        };
+
+        let request = Client::default()
+            .post(url.as_str())
+            .timeout(WEBHOOK_TIMEOUT)
+            .content_type("application/json")
+            .header("X-Pusher-Key", app.key.as_str())
+            .header("X-Pusher-Signature", sign(&app.secret, &webhook.body))
+            .send_body(webhook.body.clone());
+
+        ctx.spawn(
+            async move {
+                match request.await {
+                    Ok(response) if response.status().is_success() => Ok(()),
+                    Ok(response) => Err(format!("{} responded {}", url, response.status())),
+                    Err(e) => Err(format!("{}: {}", url, e)),
+                }
+            }
+            .into_actor(self)
+            .map(move |result, act, ctx| match result {
+                Ok(()) => act.delivered(webhook),
+                Err(error) => act.failed(webhook, error, false, ctx),
+            }),
+        );
+    }
+
+    fn delivered(&mut self, webhook: QueuedWebhook) {
+        self.in_flight.remove(&webhook.id);
+
+        if let Err(e) = self.outbox.lock().delivered(webhook.id) {
+            warn!("could not remove delivered webhook {}: {}", webhook.id, e);
+        }
+    }
+
+    /// Schedules another attempt, or dead-letters the webhook once it is out
+    /// of attempts or can never be delivered.
+    fn failed(
+        &mut self,
+        webhook: QueuedWebhook,
+        error: String,
+        permanent: bool,
+        ctx: &mut Context<Self>,
+    ) {
+        self.in_flight.remove(&webhook.id);
+
+        let attempts = webhook.attempts + 1;
+
+        warn!(
+            "webhook {} for app {} failed (attempt {}): {}",
+            webhook.id, webhook.app_id, attempts, error
+        );
+
+        let result = if permanent || attempts >= self.config.webhook_max_attempts {
+            self.outbox
+                .lock()
+                .dead_letter(webhook.id, attempts, &error, now_ms() as i64)
+        } else {
+            let backoff = retry_backoff(self.config.webhook_retry_backoff_ms, attempts);
+
+            ctx.run_later(backoff, |act, ctx| act.deliver_due(ctx));
+
+            self.outbox.lock().retry(
+                webhook.id,
+                attempts,
+                now_ms() as i64 + backoff.as_millis() as i64,
+                &error,
+            )
+        };
+
+        if let Err(e) = result {
+            warn!("could not reschedule webhook {}: {}", webhook.id, e);
+        }
+    }
+}
+
+impl Actor for WebhookDispatcher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(OUTBOX_POLL_INTERVAL, |act, ctx| act.deliver_due(ctx));
+
+        self.deliver_due(ctx);
+    }
+}
+
+impl Handler<Webhook> for WebhookDispatcher {
+    type Result = ();
+
+    fn handle(&mut self, msg: Webhook, ctx: &mut Self::Context) -> Self::Result {
+        let app = match self.repo.lock().find_by_id(msg.app_id) {
+            Some(app) if app.webhook_url.is_some() => app,
+            _ => return,
+        };
+
+        let events: Vec<WebhookEvent> = msg
+            .events
+            .into_iter()
+            .filter(|event| app.sends_webhook(event.name()))
+            .collect();
+
+        if events.is_empty() {
+            return;
+        }
+
+        let pending = self.pending.entry(msg.app_id).or_default();
+        let first = pending.is_empty();
+
+        pending.extend(events);
+
+        if pending.len() >= MAX_EVENTS_PER_WEBHOOK {
+            self.flush(msg.app_id, ctx);
+        } else if first {
+            let app_id = msg.app_id;
+
+            ctx.run_later(
+                Duration::from_millis(self.config.webhook_batch_window_ms),
+                move |act, ctx| act.flush(app_id, ctx),
+            );
+        }
+    }
+}
+
+/// Delay before attempt `attempts + 1`: `base_ms` doubled for every attempt
+/// already made, capped at an hour.
+fn retry_backoff(base_ms: u64, attempts: i32) -> Duration {
+    let factor = 2u64.saturating_pow(attempts.saturating_sub(1).max(0) as u32);
+
+    Duration::from_millis(base_ms.saturating_mul(factor)).min(MAX_RETRY_BACKOFF)
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App as PusherApp;
+    use crate::repository::{InMemoryAppRepo, InMemoryWebhookRepo};
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::Value;
+    use std::time::Instant;
+
+    /// Starts a webhook receiver on a free local port that answers the first
+    /// `failures` requests with a 500, returning its URL and the bodies it saw.
+    fn stand_in(failures: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let bodies = received.clone();
+
+        let server = HttpServer::new(move || {
+            let bodies = bodies.clone();
+
+            App::new().route(
+                "/",
+                web::post().to(move |body: String| {
+                    let bodies = bodies.clone();
+
+                    async move {
+                        let mut bodies = bodies.lock();
+                        bodies.push(body);
+
+                        if bodies.len() <= failures {
+                            Ok::<_, actix_web::Error>(HttpResponse::InternalServerError().finish())
+                        } else {
+                            Ok(HttpResponse::Ok().finish())
+                        }
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+
+        let url = format!("http://{}/", server.addrs()[0]);
+
+        server.run();
+
+        (url, received)
+    }
+
+    fn dispatcher(
+        url: String,
+        max_attempts: i32,
+    ) -> (
+        PusherApp,
+        Arc<Mutex<InMemoryWebhookRepo>>,
+        Addr<WebhookDispatcher>,
+    ) {
+        let mut app = PusherApp::new("webhooks".to_string());
+        app.webhook_url = Some(url);
+
+        let mut apps = InMemoryAppRepo::default();
+        apps.insert_app(&app).unwrap();
+
+        let outbox = Arc::new(Mutex::new(InMemoryWebhookRepo::default()));
+
+        let config = Config {
+            webhook_batch_window_ms: 20,
+            webhook_retry_backoff_ms: 20,
+            webhook_max_attempts: max_attempts,
+            ..Config::default()
+        };
+
+        let addr =
+            WebhookDispatcher::new(Arc::new(Mutex::new(apps)), outbox.clone(), config).start();
+
+        (app, outbox, addr)
+    }
+
+    /// Polls `done` until it holds, failing the test after a few seconds.
+    async fn wait_for(done: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out waiting for webhooks");
+
+            actix::clock::delay_for(Duration::from_millis(10)).await;
+        }
+    }
+
+    fn occupied(channel: &str) -> WebhookEvent {
+        WebhookEvent::ChannelOccupied {
+            channel: channel.to_string(),
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        assert_eq!(Duration::from_millis(100), retry_backoff(100, 1));
+        assert_eq!(Duration::from_millis(200), retry_backoff(100, 2));
+        assert_eq!(Duration::from_millis(800), retry_backoff(100, 4));
+        assert_eq!(MAX_RETRY_BACKOFF, retry_backoff(1000, 40));
+    }
+
+    #[test]
+    fn batches_events_and_retries_until_delivered() {
+        System::new("webhooks").block_on(async {
+            let (url, received) = stand_in(1);
+            let (app, outbox, addr) = dispatcher(url, 3);
+
+            addr.do_send(Webhook {
+                app_id: app.id,
+                events: vec![occupied("presence-a")],
+            });
+            addr.do_send(Webhook {
+                app_id: app.id,
+                events: vec![occupied("presence-b")],
+            });
+
+            wait_for(|| received.lock().len() == 2 && outbox.lock().due(i64::MAX, 10).is_empty())
+                .await;
+
+            let received = received.lock();
+            assert_eq!(2, received.len());
+            assert_eq!(received[0], received[1]);
+
+            let payload: Value = serde_json::from_str(&received[1]).unwrap();
+            assert_eq!(2, payload["events"].as_array().unwrap().len());
+            assert_eq!("channel_occupied", payload["events"][0]["name"]);
+
+            assert!(outbox.lock().due(i64::MAX, 10).is_empty());
+            assert!(outbox.lock().dead_letters(app.id).is_empty());
+        });
+    }
+
+    #[test]
+    fn dead_letters_after_max_attempts() {
+        System::new("webhooks").block_on(async {
+            let (url, received) = stand_in(usize::MAX);
+            let (app, outbox, addr) = dispatcher(url, 2);
+
+            addr.do_send(Webhook {
+                app_id: app.id,
+                events: vec![occupied("presence-a")],
+            });
+
+            wait_for(|| !outbox.lock().dead_letters(app.id).is_empty()).await;
+
+            assert_eq!(2, received.lock().len());
+            assert!(outbox.lock().due(i64::MAX, 10).is_empty());
+
+            let dead_letters = outbox.lock().dead_letters(app.id);
+            assert_eq!(1, dead_letters.len());
+            assert_eq!(2, dead_letters[0].attempts);
+            assert!(dead_letters[0]
+                .last_error
+                .as_deref()
+                .unwrap()
+                .contains("500"));
+        });
+    }
+}
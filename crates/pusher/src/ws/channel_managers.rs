@@ -1,121 +1,221 @@
-// pub fn get_channel_manager(ch: &Channel) -> Option<Box<dyn ChannelManger>> {
-//     match *ch {
-//         // Channel::Presence(_) => Some(Box::new(PresenceChannelManager {})),
-//         // Channel::Private(_) => Some(Box::new(PrivateChannelManager {})),
-//         // Channel::Public(_) => Some(Box::new(PublicChannelManager {})),
-//         Channel::Invalid => None
-//     }
-// }
-
-// pub trait ChannelManger {
-//     fn join(
-//         &self,
-//         adapter: &dyn Adapter,
-//         id: ,
-//         namespace: Namespace,
-//         message: &PusherSubscribeMessage,
-//     ) -> Result<(), PusherError> {
-//         let app_id = ws.app.as_ref().unwrap().id.clone();
-//
-//         adapter
-//             .namespace(&app_id)
-//             .add_to_channel(id, message.channel.to_string());
-//
-//         Ok(())
-//     }
-//
-//     fn leave(
-//         &self,
-//         adapter: &dyn Adapter,
-//         id: Socket,
-//         namespace: Namespace,
-//         message: &PusherUnsubscribeMessage,
-//     ) -> Result<(), PusherError> {
-//         let mut namespace = adapter.namespace(&ws.app.unwrap().id);
-//
-//         namespace.remove_from_channel(ws.id, message.channel.to_string());
-//
-//         Ok(())
-//     }
-// }
-//
-// pub struct PublicChannelManager {}
-//
-// impl ChannelManger for PublicChannelManager {
-//
-// }
-//
-// pub struct PrivateChannelManager {}
-//
-// impl ChannelManger for PrivateChannelManager {
-//     fn join(
-//         &self,
-//         adapter: &dyn Adapter,
-//         ws: WebSocket,
-//         message: &PusherSubscribeMessage,
-//     ) -> Result<(), PusherError> {
-//         let channel = message.channel.to_string();
-//
-//         let ap = AuthPayload::new(
-//             message.auth.clone().unwrap().clone(),
-//             ws.id.to_string(),
-//             channel.clone(),
-//             None,
-//         );
-//
-//         let app = ws.app.as_ref().unwrap();
-//
-//         match validate_token(
-//             &app,
-//             &ap,
-//         ) {
-//             Ok(_) => {
-//                 adapter
-//                     .namespace(&app.id)
-//                     .add_to_channel(Clone::clone(&ws), channel.clone());
-//
-//                 Ok(())
-//             }
-//             Err(_) => Err(PusherError::unauth(&message.channel)),
-//         }
-//     }
-// }
-//
-// pub struct PresenceChannelManager {}
-//
-// impl ChannelManger for PresenceChannelManager {
-//     fn join(
-//         &self,
-//         adapter: &dyn Adapter,
-//         ws: WebSocket,
-//         message: &PusherSubscribeMessage,
-//     ) -> Result<(), PusherError> {
-//         let channel = message.channel.to_string();
-//
-//         let ap = AuthPayload::new(
-//             message.auth.as_ref().unwrap().clone(),
-//             ws.id.to_string(),
-//             channel.clone(),
-//             message.channel_data.clone(),
-//         );
-//
-//         let app = ws.app.as_ref().unwrap().clone();
-//
-//         match validate_token(
-//             &app,
-//             &ap,
-//         ) {
-//             Ok(_) => {
-//                 let app_id = ws.app.as_ref().unwrap().id.clone();
-//
-//                 adapter
-//                     .namespace(&app.id)
-//                     .add_to_channel(Clone::clone(&ws), channel.clone());
-//
-//                 Ok(())
-//
-//             }
-//             Err(_) => Err(PusherError::unauth(&message.channel)),
-//         }
-//     }
-// }
+use crate::app::App;
+use crate::auth::{validate_token, AuthPayload};
+use crate::kind::Channel;
+use crate::messages::PusherMessageChannelData;
+use crate::socket::Socket;
+use crate::ws::errors::PusherSubscriptionError;
+use crate::ws::messages::PusherSubscribeMessage;
+
+pub fn get_channel_manager(ch: &Channel) -> Option<Box<dyn ChannelManager>> {
+    match *ch {
+        Channel::Presence(_) => Some(Box::new(PresenceChannelManager {})),
+        Channel::Private(_) | Channel::PrivateEncrypted(_) => {
+            Some(Box::new(PrivateChannelManager {}))
+        }
+        Channel::Cache(_) | Channel::Public(_) => Some(Box::new(PublicChannelManager {})),
+        Channel::ServerToUser(_) | Channel::Invalid => None,
+    }
+}
+
+pub trait ChannelManager {
+    /// Decides whether socket `id` may join the channel in `message`, returning
+    /// the member data to store for presence channels.
+    fn join(
+        &self,
+        app: &App,
+        id: usize,
+        message: &PusherSubscribeMessage,
+    ) -> Result<Option<PusherMessageChannelData>, PusherSubscriptionError>;
+}
+
+pub struct PublicChannelManager {}
+
+impl ChannelManager for PublicChannelManager {
+    fn join(
+        &self,
+        _app: &App,
+        _id: usize,
+        _message: &PusherSubscribeMessage,
+    ) -> Result<Option<PusherMessageChannelData>, PusherSubscriptionError> {
+        Ok(None)
+    }
+}
+
+pub struct PrivateChannelManager {}
+
+impl ChannelManager for PrivateChannelManager {
+    fn join(
+        &self,
+        app: &App,
+        id: usize,
+        message: &PusherSubscribeMessage,
+    ) -> Result<Option<PusherMessageChannelData>, PusherSubscriptionError> {
+        authorize(app, id, message, None)?;
+
+        Ok(None)
+    }
+}
+
+pub struct PresenceChannelManager {}
+
+impl ChannelManager for PresenceChannelManager {
+    fn join(
+        &self,
+        app: &App,
+        id: usize,
+        message: &PusherSubscribeMessage,
+    ) -> Result<Option<PusherMessageChannelData>, PusherSubscriptionError> {
+        let channel_data = message.channel_data.clone().ok_or_else(|| {
+            PusherSubscriptionError::unauthorized(&message.channel, "missing channel_data")
+        })?;
+
+        authorize(app, id, message, Some(channel_data.clone()))?;
+
+        let member =
+            serde_json::from_str::<PusherMessageChannelData>(&channel_data).map_err(|_| {
+                PusherSubscriptionError::unauthorized(&message.channel, "invalid channel_data")
+            })?;
+
+        if member.user_info.to_string().len() > app.max_presence_user_info_size.max(0) as usize {
+            return Err(PusherSubscriptionError::limit_reached(
+                &message.channel,
+                &format!(
+                    "user_info exceeds the maximum size of {} bytes",
+                    app.max_presence_user_info_size
+                ),
+            ));
+        }
+
+        Ok(Some(member))
+    }
+}
+
+/// Checks the `auth` signature over `socket_id:channel[:channel_data]`.
+fn authorize(
+    app: &App,
+    id: usize,
+    message: &PusherSubscribeMessage,
+    channel_data: Option<String>,
+) -> Result<(), PusherSubscriptionError> {
+    let signature = message.auth.clone().ok_or_else(|| {
+        PusherSubscriptionError::unauthorized(&message.channel, "missing auth signature")
+    })?;
+
+    let auth_payload = AuthPayload::new(
+        signature,
+        Socket { id }.to_string(),
+        message.channel.to_string(),
+        channel_data,
+    );
+
+    validate_token(app, &auth_payload)
+        .map_err(|e| PusherSubscriptionError::unauthorized(&message.channel, e.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::sign;
+    use serde_json::json;
+
+    fn subscribe(app: &App, id: usize, channel_data: String) -> PusherSubscribeMessage {
+        let channel = Channel::from("presence-room".to_string());
+
+        let signature = sign(
+            &app.secret,
+            &format!(
+                "{}:{}:{}",
+                Socket { id }.to_string(),
+                channel.to_string(),
+                channel_data
+            ),
+        );
+
+        PusherSubscribeMessage {
+            channel,
+            auth: Some(format!("{}:{}", app.key, signature)),
+            channel_data: Some(channel_data),
+        }
+    }
+
+    /// A subscription to `private-room` whose `auth` signs `signed`.
+    fn private(app: &App, signed: &str) -> PusherSubscribeMessage {
+        PusherSubscribeMessage {
+            channel: Channel::from("private-room".to_string()),
+            auth: Some(format!("{}:{}", app.key, sign(&app.secret, signed))),
+            channel_data: None,
+        }
+    }
+
+    #[test]
+    fn authorizes_private_channels() {
+        let app = App::new("auth".to_string());
+        let id = Socket::default().id;
+        let socket_id = Socket { id }.to_string();
+
+        let valid = private(&app, &format!("{}:private-room", socket_id));
+        assert!(PrivateChannelManager {}.join(&app, id, &valid).is_ok());
+
+        let tampered = private(&app, &format!("{}:private-other", socket_id));
+        assert!(PrivateChannelManager {}.join(&app, id, &tampered).is_err());
+
+        let missing = PusherSubscribeMessage {
+            auth: None,
+            ..valid
+        };
+        let error = PrivateChannelManager {}
+            .join(&app, id, &missing)
+            .unwrap_err();
+
+        assert_eq!(
+            "AuthError",
+            serde_json::to_value(&error).unwrap()["data"]["type"]
+        );
+    }
+
+    #[test]
+    fn requires_channel_data_on_presence_channels() {
+        let app = App::new("auth".to_string());
+        let id = Socket::default().id;
+
+        let channel_data = json!({"user_id": "1"}).to_string();
+        let message = PusherSubscribeMessage {
+            channel_data: None,
+            ..subscribe(&app, id, channel_data)
+        };
+
+        assert!(PresenceChannelManager {}.join(&app, id, &message).is_err());
+    }
+
+    #[test]
+    fn rejects_channels_clients_cannot_join() {
+        assert!(get_channel_manager(&Channel::server_to_user("1")).is_none());
+        assert!(get_channel_manager(&Channel::Invalid).is_none());
+    }
+
+    #[test]
+    fn limits_presence_user_info_size() {
+        let mut app = App::new("limits".to_string());
+        app.max_presence_user_info_size = 32;
+
+        let id = Socket::default().id;
+
+        let small = json!({"user_id": "1", "user_info": {"name": "Ada"}}).to_string();
+        let member = PresenceChannelManager {}
+            .join(&app, id, &subscribe(&app, id, small))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!("1", member.user_id);
+
+        let large = json!({"user_id": "1", "user_info": {"name": "A".repeat(64)}}).to_string();
+        let error = PresenceChannelManager {}
+            .join(&app, id, &subscribe(&app, id, large))
+            .unwrap_err();
+
+        assert_eq!(
+            "LimitReached",
+            serde_json::to_value(&error).unwrap()["data"]["type"]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::kind::Channel;
 use crate::messages::{JsonMessage, OutgoingMessage};
 use serde::Serialize;
 use std::error::Error;
@@ -12,33 +13,53 @@ pub trait WsError: Error + Sync + Send {
         None
     }
 
-    fn msg(&self) -> OutgoingMessage;
+    /// The WebSocket close code to end the connection with, if the error
+    /// should close it at all.
+    fn close_code(&self) -> Option<u16> {
+        None
+    }
 }
 
-#[derive(Serialize, JsonMessage)]
-struct PusherSubscriptionError {
+#[derive(Debug, Serialize, JsonMessage)]
+pub struct PusherSubscriptionError {
     event: &'static str,
+    channel: String,
     data: PusherSubscriptionErrorData,
 }
 
 #[derive(Debug, Serialize)]
 struct PusherSubscriptionErrorData {
+    #[serde(rename = "type")]
     kind: &'static str,
-    error: &'static str,
+    error: String,
     status: i32,
 }
 
-impl Default for PusherSubscriptionError {
-    fn default() -> Self {
+impl PusherSubscriptionError {
+    pub fn unauthorized(channel: &Channel, error: &str) -> Self {
+        Self::new(channel, "AuthError", error)
+    }
+
+    /// The channel or the member's data exceeds one of the app's limits.
+    pub fn limit_reached(channel: &Channel, error: &str) -> Self {
+        Self::new(channel, "LimitReached", error)
+    }
+
+    fn new(channel: &Channel, kind: &'static str, error: &str) -> Self {
         Self {
             event: "pusher:subscription_error",
+            channel: channel.to_string(),
             data: PusherSubscriptionErrorData {
-                kind: "AuthError",
-                error: "Not authorized",
+                kind,
+                error: error.to_string(),
                 status: 403,
             },
         }
     }
+
+    pub fn msg(self) -> OutgoingMessage {
+        OutgoingMessage(Box::new(self))
+    }
 }
 
 #[derive(Debug, Serialize, JsonMessage)]
@@ -53,14 +74,43 @@ impl Display for PusherSystemError {
     }
 }
 
+impl PusherSystemError {
+    /// A `pusher:error` without a close code, used to reject a single message
+    /// while keeping the connection open.
+    pub fn rejected(error: &str) -> OutgoingMessage {
+        OutgoingMessage(Box::new(PusherSystemError {
+            event: "pusher:error",
+            data: PusherSystemErrorData {
+                error: error.to_string(),
+                status: None,
+            },
+        }))
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct PusherSystemErrorData {
+    #[serde(rename = "message")]
     error: String,
+    #[serde(rename = "code")]
     status: Option<i32>,
 }
 
+/// How pusher-js reacts to an error, determined by the range its code is in.
+#[derive(Debug, PartialEq)]
+pub enum ErrorClass {
+    /// 4000-4099: the connection is closed and must not be reopened.
+    DoNotReconnect,
+    /// 4100-4199: the connection is closed and reopened after backing off.
+    ReconnectWithBackoff,
+    /// 4200-4299: the connection is closed and reopened immediately.
+    Reconnect,
+    /// 4300-4399: the offending message is rejected, the connection stays open.
+    Rejected,
+}
+
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ErrorKind {
     AppRequiresSsl,
     AppNotFound,
@@ -78,6 +128,7 @@ pub enum ErrorKind {
     PongNotReceived,
     ClosedAfterInactivity,
 
+    InvalidMessage,
     ExceededRateLimit,
 }
 
@@ -91,7 +142,7 @@ impl Error for ErrorKind {}
 
 impl WsError for ErrorKind {
     fn is_fatal(&self) -> bool {
-        matches!(self, ErrorKind::AppNotFound)
+        self.class() == ErrorClass::DoNotReconnect
     }
 
     fn to_msg(&self) -> Option<String> {
@@ -107,14 +158,11 @@ impl WsError for ErrorKind {
         )
     }
 
-    fn msg(&self) -> OutgoingMessage {
-        OutgoingMessage(Box::new(PusherSystemError {
-            event: self.to_event(),
-            data: PusherSystemErrorData {
-                error: self.to_message(),
-                status: self.to_code(),
-            },
-        }))
+    fn close_code(&self) -> Option<u16> {
+        match self.class() {
+            ErrorClass::Rejected => None,
+            _ => self.to_code().map(|code| code as u16),
+        }
     }
 }
 
@@ -134,6 +182,7 @@ impl ErrorKind {
             ErrorKind::GenericReconnectImmediately => Some(4200),
             ErrorKind::PongNotReceived => Some(4201),
             ErrorKind::ClosedAfterInactivity => Some(4202),
+            ErrorKind::InvalidMessage => Some(4300),
             ErrorKind::ExceededRateLimit => Some(4301),
         }
     }
@@ -142,10 +191,72 @@ impl ErrorKind {
         "pusher:error"
     }
 
+    pub fn class(&self) -> ErrorClass {
+        match self.to_code() {
+            Some(4000..=4099) => ErrorClass::DoNotReconnect,
+            Some(4100..=4199) => ErrorClass::ReconnectWithBackoff,
+            Some(4200..=4299) => ErrorClass::Reconnect,
+            _ => ErrorClass::Rejected,
+        }
+    }
+
     fn to_message(&self) -> String {
         match self {
-            ErrorKind::AppNotFound => "App key does not exist".to_string(),
-            _ => todo!(),
+            ErrorKind::AppRequiresSsl => {
+                "Application only accepts SSL connections, reconnect using wss://"
+            }
+            ErrorKind::AppNotFound => "App key does not exist",
+            ErrorKind::AppDisabled => "Application is disabled",
+            ErrorKind::AppOverConnectionQuota => "Application is over connection quota",
+            ErrorKind::PathNotFound => "Path not found",
+            ErrorKind::InvalidVersionStringFormat => "Invalid version string format",
+            ErrorKind::UnsupportedProtocolVersion => "Unsupported protocol version",
+            ErrorKind::NoProtocolVersionSupplied => "No protocol version supplied",
+            ErrorKind::ConnectionUnauthorized => "Connection is unauthorized",
+            ErrorKind::OverCapacity => "Over capacity",
+            ErrorKind::GenericReconnectImmediately => "Generic reconnect immediately",
+            ErrorKind::PongNotReceived => "Pong reply not received",
+            ErrorKind::ClosedAfterInactivity => "Closed after inactivity",
+            ErrorKind::InvalidMessage => "Message could not be parsed",
+            ErrorKind::ExceededRateLimit => "Client event rejected due to rate limit",
         }
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors_by_code_range() {
+        assert!(ErrorKind::AppNotFound.is_fatal());
+        assert_eq!(
+            ErrorClass::ReconnectWithBackoff,
+            ErrorKind::OverCapacity.class()
+        );
+        assert_eq!(ErrorClass::Reconnect, ErrorKind::PongNotReceived.class());
+        assert_eq!(ErrorClass::Rejected, ErrorKind::ExceededRateLimit.class());
+
+        assert_eq!(Some(4001), ErrorKind::AppNotFound.close_code());
+        assert_eq!(None, ErrorKind::ExceededRateLimit.close_code());
+        assert_eq!(None, ErrorKind::InvalidMessage.close_code());
+    }
+
+    #[test]
+    fn serializes_pusher_errors() {
+        let message: serde_json::Value =
+            serde_json::from_str(&ErrorKind::AppDisabled.to_msg().unwrap()).unwrap();
+
+        assert_eq!(
+            serde_json::json!({
+                "event": "pusher:error",
+                "data": {
+                    "message": "Application is disabled",
+                    "code": 4003,
+                }
+            }),
+            message
+        );
     }
 }
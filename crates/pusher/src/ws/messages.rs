@@ -1,8 +1,8 @@
 use crate::kind::Channel;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
 
 use crate::messages::JsonMessage;
-use crate::messages::PusherMessageChannelData;
+use crate::messages::{deserialize_string_from_number, PusherMessageChannelData};
 use crate::OutgoingMessage;
 use serde_json::json;
 use std::collections::HashMap;
@@ -10,7 +10,7 @@ use std::collections::HashMap;
 pub struct PusherSubscribeMessage {
     pub channel: Channel,
     pub auth: Option<String>,
-    pub channel_data: Option<PusherMessageChannelData>,
+    pub channel_data: Option<String>,
 }
 
 pub struct PusherUnsubscribeMessage {
@@ -37,6 +37,24 @@ pub enum ChannelEvent {
         channel: String,
         data: PresenceMemberRemovedData,
     },
+    #[serde(rename = "pusher:cache_miss")]
+    PusherCacheMiss { channel: String },
+    #[serde(rename = "pusher:signin_success")]
+    PusherSigninSuccess {
+        #[serde(with = "serde_with::json::nested")]
+        data: SigninSuccessData,
+    },
+    #[serde(rename = "pusher_internal:subscription_count")]
+    PusherInternalSubscriptionCount {
+        channel: String,
+        #[serde(with = "serde_with::json::nested")]
+        data: SubscriptionCountData,
+    },
+    #[serde(rename = "pusher_internal:watchlist_events")]
+    PusherInternalWatchlistEvents {
+        #[serde(with = "serde_with::json::nested")]
+        data: WatchlistEventsData,
+    },
     #[serde(rename = "pusher:connection_established")]
     PusherConnectionEstablished { data: ConnectionEstablishedData },
     #[serde(rename = "pusher:pong")]
@@ -96,16 +114,50 @@ impl ChannelEvent {
         .msg()
     }
 
-    pub fn member_removed(channel: &Channel, id: usize) -> OutgoingMessage {
-        Self::PusherInternalMemberRemoved {
+    pub fn cache_miss(channel: &Channel) -> OutgoingMessage {
+        Self::PusherCacheMiss {
             channel: channel.to_string(),
-            data: PresenceMemberRemovedData {
-                user_id: id.to_string(),
+        }
+        .msg()
+    }
+
+    pub fn signin_success(user_data: String) -> OutgoingMessage {
+        Self::PusherSigninSuccess {
+            data: SigninSuccessData { user_data },
+        }
+        .msg()
+    }
+
+    pub fn subscription_count(channel: &Channel, subscription_count: usize) -> OutgoingMessage {
+        Self::PusherInternalSubscriptionCount {
+            channel: channel.to_string(),
+            data: SubscriptionCountData { subscription_count },
+        }
+        .msg()
+    }
+
+    /// Tells a signed in user that users on their watchlist came `online` or
+    /// went `offline`.
+    pub fn watchlist_events(name: &str, user_ids: Vec<String>) -> OutgoingMessage {
+        Self::PusherInternalWatchlistEvents {
+            data: WatchlistEventsData {
+                events: vec![WatchlistEvent {
+                    name: name.to_string(),
+                    user_ids,
+                }],
             },
         }
         .msg()
     }
 
+    pub fn member_removed(channel: &Channel, user_id: String) -> OutgoingMessage {
+        Self::PusherInternalMemberRemoved {
+            channel: channel.to_string(),
+            data: PresenceMemberRemovedData { user_id },
+        }
+        .msg()
+    }
+
     fn msg(&self) -> OutgoingMessage {
         OutgoingMessage(Box::new(self.clone()))
     }
@@ -117,6 +169,27 @@ impl ToString for ChannelEvent {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SigninSuccessData {
+    pub user_data: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubscriptionCountData {
+    pub subscription_count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchlistEventsData {
+    pub events: Vec<WatchlistEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchlistEvent {
+    pub name: String,
+    pub user_ids: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PresenceMemberRemovedData {
     pub user_id: String,
@@ -160,25 +233,6 @@ where
     s.serialize_str(v.as_str())
 }
 
-pub fn deserialize_string_from_number<'de, D>(deserializer: D) -> Result<String, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    #[derive(Deserialize)]
-    #[serde(untagged)]
-    enum StringOrNumber {
-        String(String),
-        Number(i64),
-        Float(f64),
-    }
-
-    match StringOrNumber::deserialize(deserializer)? {
-        StringOrNumber::String(s) => Ok(s),
-        StringOrNumber::Number(i) => Ok(i.to_string()),
-        StringOrNumber::Float(f) => Ok(f.to_string()),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -186,27 +240,19 @@ mod tests {
 
     #[test]
     fn presence_serialize() {
-        let hash = HashMap::default();
-        let count = 1;
-        let ids = vec!["1".to_string()];
+        let ce = ChannelEvent::presence_sub_succeeded(
+            &Channel::Presence("presence-some".to_string()),
+            vec![PusherMessageChannelData {
+                user_id: "1".to_string(),
+                user_info: json!({}),
+            }],
+        );
 
-        let ce = ChannelEvent::PusherInternalSubscriptionSucceeded {
-            channel: "presence-some".to_string(),
-            data: SubscriptionData {
-                presence: Some(PresenceInternalData { count, ids, hash }),
-            },
-        };
-
-        let result = serde_json::to_value(ce).unwrap();
-
-        let _t = serde_json::to_string(&json!({
-            "what": "hello",
-        }))
-        .unwrap();
+        let result = serde_json::to_value(&ce.0).unwrap();
 
         let s: String = json!({
             "presence": {
-                "ids": [1_usize],
+                "ids": ["1"],
                 "count": 1_usize,
                 "hash": {"1": {}},
             }
@@ -219,6 +265,36 @@ mod tests {
             "data": s
         });
 
-        assert_eq!(expected, result);
+        assert_eq!(expected["event"], result["event"]);
+        assert_eq!(expected["channel"], result["channel"]);
+
+        let data: serde_json::Value =
+            serde_json::from_str(result["data"].as_str().unwrap()).unwrap();
+        let expected_data: serde_json::Value =
+            serde_json::from_str(expected["data"].as_str().unwrap()).unwrap();
+
+        assert_eq!(expected_data, data);
+    }
+
+    #[test]
+    fn accepts_numeric_user_ids() {
+        let data: PusherMessageChannelData =
+            serde_json::from_str(r#"{"user_id": 42, "user_info": {}}"#).unwrap();
+
+        assert_eq!("42", data.user_id);
+    }
+
+    #[test]
+    fn subscription_count_serialize() {
+        let ce = ChannelEvent::subscription_count(&Channel::Public("room-1".to_string()), 3);
+
+        let result = serde_json::to_value(&ce.0).unwrap();
+
+        assert_eq!("pusher_internal:subscription_count", result["event"]);
+        assert_eq!("room-1", result["channel"]);
+        assert_eq!(
+            r#"{"subscription_count":3}"#,
+            result["data"].as_str().unwrap()
+        );
     }
 }
@@ -1,13 +1,19 @@
 use crate::adapter::Adapter;
 use crate::app::App;
+use crate::auth::{validate_token, AuthPayload};
+use crate::config::Config;
 use crate::kind::{Channel, Event};
-use crate::messages::PusherMessage;
+use crate::messages::{PusherMessage, PusherMessageChannelData, PusherSigninUserData};
 use crate::socket::Socket;
 
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::messages::{JsonMessage, OutgoingMessage};
-use crate::ws::errors::{ErrorKind, WsError};
+use crate::webhooks::{Webhook, WebhookEvent};
+use crate::ws::channel_managers::get_channel_manager;
+use crate::ws::errors::{ErrorKind, PusherSubscriptionError, PusherSystemError, WsError};
 use crate::ws::messages::{ChannelEvent, PusherSubscribeMessage, PusherUnsubscribeMessage};
 use crate::{AppRepo, WebSocket};
 use actix::prelude::*;
@@ -17,7 +23,7 @@ use parking_lot::Mutex;
 use serde::Serialize;
 
 mod channel_managers;
-mod errors;
+pub mod errors;
 mod messages;
 
 #[derive(Message)]
@@ -27,12 +33,35 @@ pub struct Disconnect {
     pub app_id: i64,
 }
 
+/// Closes a session with `error`.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Terminate {
+    pub error: ErrorKind,
+}
+
+/// Closes every connection signed in as the user.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct TerminateUserConnections {
+    pub app_id: i64,
+    pub user_id: String,
+}
+
+/// Closes every connection to the app, after it was disabled or deleted.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct TerminateAppConnections {
+    pub app_id: i64,
+    pub error: ErrorKind,
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Broadcast {
     pub event: String,
     pub channels: Vec<String>,
-    pub message: serde_json::Value,
+    pub message: String,
     pub app: App,
     pub except: Option<usize>,
 }
@@ -44,19 +73,60 @@ pub struct MessageWrapper {
     pub message: PusherMessage,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ClientEvent {
+    pub ws: WebSocket,
+    pub event: String,
+    pub channel: Option<String>,
+    pub data: serde_json::Value,
+}
+
 #[derive(Clone)]
 pub struct WebSocketHandler {
     adapter: Arc<dyn Adapter>,
     repo: Arc<Mutex<dyn AppRepo>>,
+    webhooks: Recipient<Webhook>,
+    config: Config,
+    sessions: HashMap<usize, Recipient<Terminate>>,
+    /// Channels whose subscription count changed since the last
+    /// `pusher_internal:subscription_count` was sent.
+    changed_counts: HashSet<(i64, Channel)>,
 }
 
 impl Actor for WebSocketHandler {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(
+            Duration::from_millis(self.config.subscription_count_interval_ms),
+            |act, _ctx| act.send_subscription_counts(),
+        );
+    }
 }
 
 impl WebSocketHandler {
-    pub fn new(adapter: Arc<dyn Adapter>, repo: Arc<Mutex<dyn AppRepo>>) -> Self {
-        Self { adapter, repo }
+    pub fn new(
+        adapter: Arc<dyn Adapter>,
+        repo: Arc<Mutex<dyn AppRepo>>,
+        webhooks: Recipient<Webhook>,
+        config: Config,
+    ) -> Self {
+        Self {
+            adapter,
+            repo,
+            webhooks,
+            config,
+            sessions: HashMap::new(),
+            changed_counts: HashSet::new(),
+        }
+    }
+
+    fn webhook(&self, app_id: i64, event: WebhookEvent) {
+        let _ = self.webhooks.do_send(Webhook {
+            app_id,
+            events: vec![event],
+        });
     }
 
     fn subscribe(
@@ -66,24 +136,61 @@ impl WebSocketHandler {
         recipient: Recipient<OutgoingMessage>,
         m: PusherSubscribeMessage,
     ) {
+        let presence_data = match get_channel_manager(&m.channel) {
+            Some(manager) => manager.join(&app, id, &m),
+            None => Err(PusherSubscriptionError::unauthorized(
+                &m.channel,
+                "invalid channel",
+            )),
+        };
+
+        let presence_data = match presence_data {
+            Ok(presence_data) => presence_data,
+            Err(e) => {
+                recipient.do_send(e.msg()).unwrap();
+
+                return;
+            }
+        };
+
         let ns = self.adapter.namespace(app.id);
 
-        // // do authentication then
-        // if let Err(e) = result {
-        //     ws.conn.send(OutgoingMessage(e.to_string()));
-        //
-        //     return;
-        // }
+        if let Some(member) = &presence_data {
+            if !ns.has_user(&m.channel, &member.user_id)
+                && ns.users_per_channel(&m.channel) >= app.max_presence_members.max(0) as usize
+            {
+                recipient
+                    .do_send(
+                        PusherSubscriptionError::limit_reached(
+                            &m.channel,
+                            &format!(
+                                "presence channel is limited to {} members",
+                                app.max_presence_members
+                            ),
+                        )
+                        .msg(),
+                    )
+                    .unwrap();
+
+                return;
+            }
+        }
+
+        ns.add_socket(id, Clone::clone(&recipient));
+
+        let new_member = presence_data
+            .as_ref()
+            .filter(|data| !ns.has_user(&m.channel, &data.user_id))
+            .map(|data| data.user_id.clone());
+
+        let count = ns.add_to_channel(id, &m.channel, presence_data);
 
         match m.channel {
             Channel::Presence(_) => {
                 recipient
                     .do_send(ChannelEvent::presence_sub_succeeded(
                         &m.channel,
-                        ns.channel_members(&m.channel)
-                            .iter()
-                            .map(|(_, m)| m.clone())
-                            .collect(),
+                        ns.channel_members(&m.channel),
                     ))
                     .unwrap();
             }
@@ -94,25 +201,64 @@ impl WebSocketHandler {
             }
         };
 
-        ns.add_socket(id, Clone::clone(&recipient));
+        if m.channel.is_cache() {
+            let ttl = Duration::from_secs(self.config.cache_channel_ttl);
+
+            match ns.cached_event(&m.channel, ttl) {
+                Some(cached) => recipient
+                    .do_send(OutgoingMessage(Box::new(OutgoingBroadcast {
+                        channel: m.channel.to_string(),
+                        event: cached.event,
+                        data: cached.data,
+                    })))
+                    .unwrap(),
+                None => {
+                    recipient
+                        .do_send(ChannelEvent::cache_miss(&m.channel))
+                        .unwrap();
+
+                    self.webhook(
+                        app.id,
+                        WebhookEvent::CacheMiss {
+                            channel: m.channel.to_string(),
+                        },
+                    );
+                }
+            }
+        }
+
+        if count.is_some() {
+            count_changed(&mut self.changed_counts, app.id, &m.channel);
+        }
 
-        let _count = ns.add_to_channel(id, &m.channel, m.channel_data);
+        if count == Some(1) {
+            self.webhook(
+                app.id,
+                WebhookEvent::ChannelOccupied {
+                    channel: m.channel.to_string(),
+                },
+            );
+        }
 
-        if matches!(m.channel, Channel::Presence(_)) {
+        if let Some(user_id) = new_member {
             let presence_data = ns.get_presence_data(id, &m.channel).unwrap();
 
             for (recipient_id, recipient) in ns.channel_sockets(&m.channel) {
-                println!("wanna send: {}", recipient_id);
                 if id != recipient_id {
-                    println!("is sending");
-                    recipient
-                        .do_send(ChannelEvent::member_added(
-                            &m.channel,
-                            presence_data.clone(),
-                        ))
-                        .unwrap();
+                    let _ = recipient.do_send(ChannelEvent::member_added(
+                        &m.channel,
+                        presence_data.clone(),
+                    ));
                 }
             }
+
+            self.webhook(
+                app.id,
+                WebhookEvent::MemberAdded {
+                    channel: m.channel.to_string(),
+                    user_id,
+                },
+            );
         }
     }
 
@@ -123,41 +269,364 @@ impl WebSocketHandler {
         _recipient: Recipient<OutgoingMessage>,
         m: PusherUnsubscribeMessage,
     ) {
-        self.adapter
-            .namespace(app.id)
-            .remove_from_channel(id, &m.channel);
+        self.leave(id, app.id, &m.channel);
+    }
+
+    /// Removes the socket from the channel and notifies everyone interested.
+    fn leave(&mut self, id: usize, app_id: i64, channel: &Channel) {
+        let ns = self.adapter.namespace(app_id);
 
-        self.notify_unsubscribed(id, app.id, &m.channel);
+        let member = ns.get_presence_data(id, channel);
+
+        let remaining = ns.remove_from_channel(id, channel);
+
+        let member_left = member.filter(|data| !ns.has_user(channel, &data.user_id));
+
+        drop(ns);
+
+        if remaining.is_some() {
+            count_changed(&mut self.changed_counts, app_id, channel);
+
+            self.notify_unsubscribed(id, app_id, channel, remaining, member_left);
+        }
     }
 
-    fn notify_unsubscribed(&self, id: usize, app_id: i64, channel: &Channel) {
-        trace!("{}: begin notify unsubscribed to channel:{}", id, channel.to_string());
+    /// Sends the current count of every changed channel to its subscribers,
+    /// at most once per `subscription_count_interval_ms`.
+    fn send_subscription_counts(&mut self) {
+        let mut changed: HashMap<i64, Vec<Channel>> = HashMap::new();
+
+        for (app_id, channel) in self.changed_counts.drain() {
+            changed.entry(app_id).or_default().push(channel);
+        }
+
+        for (app_id, channels) in changed {
+            let enabled = self
+                .repo
+                .lock()
+                .find_by_id(app_id)
+                .map(|app| app.enable_subscription_count)
+                .unwrap_or(false);
+
+            if !enabled {
+                continue;
+            }
+
+            let ns = self.adapter.namespace(app_id);
+
+            let mut events = Vec::new();
+
+            for channel in channels {
+                let sockets = ns.channel_sockets(&channel);
+
+                if sockets.is_empty() {
+                    continue;
+                }
+
+                for (_, recipient) in sockets.iter() {
+                    let _ = recipient
+                        .do_send(ChannelEvent::subscription_count(&channel, sockets.len()));
+                }
+
+                events.push(WebhookEvent::SubscriptionCount {
+                    channel: channel.to_string(),
+                    subscription_count: sockets.len(),
+                });
+            }
+
+            drop(ns);
+
+            if !events.is_empty() {
+                let _ = self.webhooks.do_send(Webhook { app_id, events });
+            }
+        }
+    }
+
+    fn notify_unsubscribed(
+        &self,
+        id: usize,
+        app_id: i64,
+        channel: &Channel,
+        remaining: Option<usize>,
+        member_left: Option<PusherMessageChannelData>,
+    ) {
+        if remaining == Some(0) {
+            self.webhook(
+                app_id,
+                WebhookEvent::ChannelVacated {
+                    channel: channel.to_string(),
+                },
+            );
+        }
+
+        let member_left = member_left.map(|member| member.user_id);
+
+        if let Some(user_id) = &member_left {
+            self.webhook(
+                app_id,
+                WebhookEvent::MemberRemoved {
+                    channel: channel.to_string(),
+                    user_id: user_id.clone(),
+                },
+            );
+        }
+
+        trace!(
+            "{}: begin notify unsubscribed to channel:{}",
+            id,
+            channel.to_string()
+        );
 
         let ns = self.adapter.namespace(app_id);
 
-        trace!("{}: checking if presence channel:{}", id, channel.to_string());
+        trace!(
+            "{}: checking if presence member left channel:{}",
+            id,
+            channel.to_string()
+        );
 
-        if matches!(channel, Channel::Presence(_)) {
+        if let Some(user_id) = member_left {
             let recipients = ns.channel_sockets(channel);
 
             for (recipient_id, recipient) in recipients {
                 if id != recipient_id {
                     trace!("{}: notifying unsubscribed to {}", id, recipient_id);
-                    recipient
-                        .try_send(ChannelEvent::member_removed(channel, id))
-                        .unwrap();
+                    let _ =
+                        recipient.do_send(ChannelEvent::member_removed(channel, user_id.clone()));
                     trace!("{}: notified unsubscribed to {}", id, recipient_id);
                 }
             }
         }
     }
 
+    /// Signs the connection in as the user in `user_data`, closing it with
+    /// 4009 when the signature or the user data is invalid.
+    fn signin(
+        &self,
+        ws: WebSocket,
+        app: App,
+        auth: Option<String>,
+        user_data: Option<String>,
+    ) -> Result<(), Box<dyn WsError>> {
+        let (auth, user_data) = match (auth, user_data) {
+            (Some(auth), Some(user_data)) => (auth, user_data),
+            _ => return Err(Box::new(ErrorKind::ConnectionUnauthorized)),
+        };
+
+        let auth_payload =
+            AuthPayload::signin(auth, Socket { id: ws.id }.to_string(), user_data.clone());
+
+        if validate_token(&app, &auth_payload).is_err() {
+            return Err(Box::new(ErrorKind::ConnectionUnauthorized));
+        }
+
+        let user = match serde_json::from_str::<PusherSigninUserData>(&user_data) {
+            Ok(user) if !user.id.is_empty() => user,
+            _ => return Err(Box::new(ErrorKind::ConnectionUnauthorized)),
+        };
+
+        let ns = self.adapter.namespace(app.id);
+
+        // signing in again as the same user keeps them online
+        if ns.user_of(ws.id).as_deref() != Some(user.id.as_str()) {
+            drop(ns);
+
+            self.sign_out(app.id, ws.id);
+        }
+
+        let ns = self.adapter.namespace(app.id);
+
+        let first_socket = ns.sign_in(ws.id, &user.id);
+
+        ns.set_watchlist(&user.id, &user.watchlist);
+
+        let online: Vec<String> = user
+            .watchlist
+            .iter()
+            .filter(|watched| ns.is_online(watched))
+            .cloned()
+            .collect();
+
+        drop(ns);
+
+        ws.conn
+            .do_send(ChannelEvent::signin_success(user_data))
+            .unwrap();
+
+        if !user.watchlist.is_empty() {
+            ws.conn
+                .do_send(ChannelEvent::watchlist_events("online", online))
+                .unwrap();
+        }
+
+        if first_socket {
+            self.notify_watchers(app.id, &user.id, true);
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the socket's user, telling their watchers they went offline
+    /// when it was the user's last connection.
+    fn sign_out(&self, app_id: i64, id: usize) {
+        let ns = self.adapter.namespace(app_id);
+
+        let offline = ns.sign_out(id).filter(|user_id| !ns.is_online(user_id));
+
+        if let Some(user_id) = &offline {
+            ns.remove_watchlist(user_id);
+        }
+
+        drop(ns);
+
+        if let Some(user_id) = offline {
+            self.notify_watchers(app_id, &user_id, false);
+        }
+    }
+
+    fn notify_watchers(&self, app_id: i64, user_id: &str, online: bool) {
+        let ns = self.adapter.namespace(app_id);
+
+        let name = if online { "online" } else { "offline" };
+
+        for watcher in ns.watchers(user_id) {
+            for (_, recipient) in ns.user_sockets(&watcher) {
+                let _ = recipient.do_send(ChannelEvent::watchlist_events(
+                    name,
+                    vec![user_id.to_string()],
+                ));
+            }
+        }
+
+        drop(ns);
+
+        let webhooks_enabled = self
+            .repo
+            .lock()
+            .find_by_id(app_id)
+            .map(|app| app.enable_watchlist_webhooks)
+            .unwrap_or(false);
+
+        if webhooks_enabled {
+            let user_id = user_id.to_string();
+
+            self.webhook(
+                app_id,
+                if online {
+                    WebhookEvent::UserOnline { user_id }
+                } else {
+                    WebhookEvent::UserOffline { user_id }
+                },
+            );
+        }
+    }
+
     fn pong(&self, ws: WebSocket) {
         ws.conn.do_send(ChannelEvent::pong()).unwrap();
     }
 
-    fn handle_client_event(&self, _ws: WebSocket) {
-        todo!()
+    fn handle_client_event(&self, msg: ClientEvent) {
+        let app = match self.repo.lock().find_by_id(msg.ws.app_id) {
+            Some(app) => app,
+            None => return,
+        };
+
+        let channel = Channel::from(msg.channel);
+
+        if !app.enable_client_events {
+            msg.ws
+                .conn
+                .do_send(PusherSystemError::rejected(
+                    "Client events are not enabled for this app",
+                ))
+                .unwrap();
+
+            return;
+        }
+
+        if matches!(channel, Channel::PrivateEncrypted(_)) {
+            msg.ws
+                .conn
+                .do_send(PusherSystemError::rejected(
+                    "Client event rejected - not supported on encrypted channels",
+                ))
+                .unwrap();
+
+            return;
+        }
+
+        if msg.data.to_string().len() > app.max_message_size as usize {
+            msg.ws
+                .conn
+                .do_send(PusherSystemError::rejected(
+                    "Client event rejected - data exceeds the app's max message size",
+                ))
+                .unwrap();
+
+            return;
+        }
+
+        if !matches!(channel, Channel::Private(_) | Channel::Presence(_)) {
+            msg.ws
+                .conn
+                .do_send(PusherSystemError::rejected(
+                    "Client event rejected - only supported on private and presence channels",
+                ))
+                .unwrap();
+
+            return;
+        }
+
+        let ns = self.adapter.namespace(app.id);
+
+        let recipients = ns.channel_sockets(&channel);
+
+        if !recipients.contains_key(&msg.ws.id) {
+            msg.ws
+                .conn
+                .do_send(PusherSystemError::rejected(
+                    "Client event rejected - not subscribed to channel",
+                ))
+                .unwrap();
+
+            return;
+        }
+
+        let user_id = match channel {
+            Channel::Presence(_) => ns
+                .get_presence_data(msg.ws.id, &channel)
+                .map(|presence_data| presence_data.user_id),
+            _ => None,
+        };
+
+        for (recipient_id, recipient) in recipients {
+            if recipient_id != msg.ws.id {
+                let _ = recipient.do_send(OutgoingMessage(Box::new(OutgoingClientEvent {
+                    channel: channel.to_string(),
+                    event: msg.event.clone(),
+                    data: msg.data.clone(),
+                    user_id: user_id.clone(),
+                })));
+            }
+        }
+
+        if app.enable_client_event_webhooks {
+            let data = match msg.data {
+                serde_json::Value::String(data) => data,
+                data => data.to_string(),
+            };
+
+            self.webhook(
+                app.id,
+                WebhookEvent::ClientEvent {
+                    channel: channel.to_string(),
+                    event: msg.event,
+                    data,
+                    socket_id: Socket { id: msg.ws.id }.to_string(),
+                    user_id,
+                },
+            );
+        }
     }
 }
 
@@ -165,6 +634,8 @@ impl WebSocketHandler {
 #[rtype(result = "Result<usize, Box<dyn WsError>>")]
 pub struct Connect {
     pub ws: WebSocket,
+    pub origin: Option<String>,
+    pub terminate: Recipient<Terminate>,
 }
 
 impl Handler<Connect> for WebSocketHandler {
@@ -174,11 +645,27 @@ impl Handler<Connect> for WebSocketHandler {
         let app = self.repo.lock().find_by_id(msg.ws.app_id);
 
         if let Some(app) = app {
-            let id = Socket::default().id;
+            if !app.enabled {
+                return Err(Box::new(ErrorKind::AppDisabled));
+            }
+
+            if !app.allows_origin(msg.origin.as_deref()) {
+                return Err(Box::new(ErrorKind::ConnectionUnauthorized));
+            }
 
             let ns = self.adapter.namespace(app.id);
 
-            ns.add_socket(msg.ws.id, Clone::clone(&msg.ws.conn));
+            if let Some(max_connections) = app.max_connections {
+                if ns.socket_count() >= max_connections.max(0) as usize {
+                    return Err(Box::new(ErrorKind::AppOverConnectionQuota));
+                }
+            }
+
+            let id = Socket::default().id;
+
+            ns.add_socket(id, Clone::clone(&msg.ws.conn));
+
+            self.sessions.insert(id, msg.terminate);
 
             msg.ws
                 .conn
@@ -196,21 +683,57 @@ impl Handler<Disconnect> for WebSocketHandler {
     type Result = ();
 
     fn handle(&mut self, msg: Disconnect, _ctx: &mut Self::Context) -> Self::Result {
-        let ns = self.adapter.namespace(msg.app_id);
-
-        let channels = ns.channels_for_member(msg.id);
+        let channels = self
+            .adapter
+            .namespace(msg.app_id)
+            .channels_for_member(msg.id);
 
         for channel in &channels {
-            ns.remove_from_channel(msg.id, channel);
+            self.leave(msg.id, msg.app_id, channel);
         }
 
-        drop(ns);
+        self.sign_out(msg.app_id, msg.id);
 
-        for channel in &channels {
-            self.notify_unsubscribed(msg.id, msg.app_id, channel);
+        self.adapter.namespace(msg.app_id).remove_socket(msg.id);
+
+        self.sessions.remove(&msg.id);
+    }
+}
+
+impl Handler<TerminateUserConnections> for WebSocketHandler {
+    type Result = ();
+
+    fn handle(&mut self, msg: TerminateUserConnections, _ctx: &mut Self::Context) -> Self::Result {
+        let ids: Vec<usize> = self
+            .adapter
+            .namespace(msg.app_id)
+            .user_sockets(&msg.user_id)
+            .into_keys()
+            .collect();
+
+        for id in ids {
+            if let Some(session) = self.sessions.get(&id) {
+                let _ = session.do_send(Terminate {
+                    error: ErrorKind::ConnectionUnauthorized,
+                });
+            }
         }
+    }
+}
 
-        self.adapter.namespace(msg.app_id).remove_socket(msg.id);
+impl Handler<TerminateAppConnections> for WebSocketHandler {
+    type Result = ();
+
+    fn handle(&mut self, msg: TerminateAppConnections, _ctx: &mut Self::Context) -> Self::Result {
+        let ids = self.adapter.namespace(msg.app_id).socket_ids();
+
+        for id in ids {
+            if let Some(session) = self.sessions.get(&id) {
+                let _ = session.do_send(Terminate {
+                    error: msg.error.clone(),
+                });
+            }
+        }
     }
 }
 
@@ -249,18 +772,19 @@ impl Handler<MessageWrapper> for WebSocketHandler {
                         },
                     );
                 }
-                Event::Client(_) => {
-                    self.handle_client_event(msg.ws);
-                }
-                Event::Invalid => {
-                    todo!();
+                Event::Signin => {
+                    return self.signin(
+                        msg.ws,
+                        app,
+                        msg.message.data.auth,
+                        msg.message.data.user_data,
+                    );
                 }
+                Event::Client(_) | Event::Invalid => (),
             };
 
             Ok(())
         } else {
-            msg.ws.conn.do_send(ErrorKind::AppNotFound.msg()).unwrap();
-
             Err(Box::new(ErrorKind::AppNotFound))
         }
     }
@@ -273,6 +797,23 @@ pub struct OutgoingBroadcast {
     data: String,
 }
 
+impl Handler<ClientEvent> for WebSocketHandler {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClientEvent, _ctx: &mut Self::Context) -> Self::Result {
+        self.handle_client_event(msg);
+    }
+}
+
+#[derive(Serialize, JsonMessage)]
+pub struct OutgoingClientEvent {
+    channel: String,
+    event: String,
+    data: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<String>,
+}
+
 impl Handler<Broadcast> for WebSocketHandler {
     type Result = ();
 
@@ -280,17 +821,215 @@ impl Handler<Broadcast> for WebSocketHandler {
         let ns = self.adapter.namespace(msg.app.id);
 
         for channel in msg.channels {
-            let sockets = ns.channel_sockets(&Channel::from(channel.clone()));
+            let kind = Channel::from(channel.clone());
 
-            for socket in sockets.values() {
-                socket
-                    .do_send(OutgoingMessage(Box::new(OutgoingBroadcast {
-                        channel: channel.clone(),
-                        event: msg.event.clone(),
-                        data: msg.message.to_string(),
-                    })))
-                    .unwrap();
+            if kind.is_cache() {
+                ns.cache_event(&kind, msg.event.clone(), msg.message.clone());
+            }
+
+            let sockets = match kind.user_id() {
+                Some(user_id) => ns.user_sockets(user_id),
+                None => ns.channel_sockets(&kind),
+            };
+
+            for (id, socket) in sockets {
+                if msg.except == Some(id) {
+                    continue;
+                }
+
+                let _ = socket.do_send(OutgoingMessage(Box::new(OutgoingBroadcast {
+                    channel: channel.clone(),
+                    event: msg.event.clone(),
+                    data: msg.message.clone(),
+                })));
             }
         }
     }
 }
+
+/// Marks a channel for the next `pusher_internal:subscription_count` round.
+/// Presence channels report their members instead.
+fn count_changed(changed: &mut HashSet<(i64, Channel)>, app_id: i64, channel: &Channel) {
+    if !matches!(channel, Channel::Presence(_)) {
+        changed.insert((app_id, channel.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Take, TestServer};
+    use actix_web::rt::System;
+    use serde_json::json;
+
+    #[test]
+    fn sends_occupied_and_vacated_once_per_channel() {
+        System::new("ws").block_on(async {
+            let app = App::new("ws".to_string());
+            let server = TestServer::start(&[&app]);
+
+            let (first, _) = server.connect(&app).await;
+            let (second, _) = server.connect(&app).await;
+
+            server.subscribe(&app, &first, "room", None).await;
+            server.subscribe(&app, &first, "room", None).await;
+            server.subscribe(&app, &second, "room", None).await;
+
+            let unsubscribe = json!({
+                "event": "pusher:unsubscribe",
+                "data": {"channel": "room"},
+            });
+
+            server.send(&first, unsubscribe.clone()).await.unwrap();
+            server.send(&second, unsubscribe).await.unwrap();
+
+            assert_eq!(
+                vec![
+                    json!({"name": "channel_occupied", "channel": "room"}),
+                    json!({"name": "channel_vacated", "channel": "room"}),
+                ],
+                server.webhooks.send(Take).await.unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn sends_member_webhooks_once_per_user() {
+        System::new("ws").block_on(async {
+            let app = App::new("ws".to_string());
+            let server = TestServer::start(&[&app]);
+
+            let (first, _) = server.connect(&app).await;
+            let (second, _) = server.connect(&app).await;
+            let (other, _) = server.connect(&app).await;
+
+            let member = |user_id: &str| Some(json!({"user_id": user_id, "user_info": {}}));
+
+            server
+                .subscribe(&app, &first, "presence-room", member("1"))
+                .await;
+            server
+                .subscribe(&app, &second, "presence-room", member("1"))
+                .await;
+            server
+                .subscribe(&app, &other, "presence-room", member("2"))
+                .await;
+
+            let unsubscribe = json!({
+                "event": "pusher:unsubscribe",
+                "data": {"channel": "presence-room"},
+            });
+
+            server.send(&first, unsubscribe.clone()).await.unwrap();
+            server.send(&second, unsubscribe).await.unwrap();
+
+            let added = |user_id: &str| {
+                json!({"name": "member_added", "channel": "presence-room", "user_id": user_id})
+            };
+
+            assert_eq!(
+                vec![
+                    json!({"name": "channel_occupied", "channel": "presence-room"}),
+                    added("1"),
+                    added("2"),
+                    json!({"name": "member_removed", "channel": "presence-room", "user_id": "1"}),
+                ],
+                server.webhooks.send(Take).await.unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn sends_client_event_webhooks() {
+        System::new("ws").block_on(async {
+            let app = App {
+                enable_client_events: true,
+                enable_client_event_webhooks: true,
+                ..App::new("ws".to_string())
+            };
+            let server = TestServer::start(&[&app]);
+
+            let (sender, _) = server.connect(&app).await;
+            let (receiver, client) = server.connect(&app).await;
+
+            server.subscribe(&app, &sender, "private-room", None).await;
+            server
+                .subscribe(&app, &receiver, "private-room", None)
+                .await;
+
+            server
+                .handler
+                .send(ClientEvent {
+                    ws: sender.clone(),
+                    event: "client-typing".to_string(),
+                    channel: Some("private-room".to_string()),
+                    data: json!({"typing": true}),
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(
+                Some(&json!({
+                    "channel": "private-room",
+                    "event": "client-typing",
+                    "data": {"typing": true},
+                })),
+                client.send(Take).await.unwrap().last()
+            );
+
+            assert_eq!(
+                vec![
+                    json!({"name": "channel_occupied", "channel": "private-room"}),
+                    json!({
+                        "name": "client_event",
+                        "channel": "private-room",
+                        "event": "client-typing",
+                        "data": r#"{"typing":true}"#,
+                        "socket_id": Socket { id: sender.id }.to_string(),
+                    }),
+                ],
+                server.webhooks.send(Take).await.unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn presence_subscribers_see_themselves() {
+        System::new("ws").block_on(async {
+            let app = App::new("ws".to_string());
+            let server = TestServer::start(&[&app]);
+
+            let (first, _) = server.connect(&app).await;
+            let (second, client) = server.connect(&app).await;
+
+            let member = |user_id: &str| Some(json!({"user_id": user_id, "user_info": {}}));
+
+            server
+                .subscribe(&app, &first, "presence-room", member("1"))
+                .await;
+            server
+                .subscribe(&app, &second, "presence-room", member("2"))
+                .await;
+
+            let received = client.send(Take).await.unwrap();
+            let succeeded = received.last().unwrap();
+
+            assert_eq!("pusher_internal:subscription_succeeded", succeeded["event"]);
+
+            let data: serde_json::Value =
+                serde_json::from_str(succeeded["data"].as_str().unwrap()).unwrap();
+
+            let mut ids: Vec<&str> = data["presence"]["ids"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|id| id.as_str().unwrap())
+                .collect();
+            ids.sort_unstable();
+
+            assert_eq!(vec!["1", "2"], ids);
+            assert_eq!(2, data["presence"]["count"]);
+            assert_eq!(json!({"1": {}, "2": {}}), data["presence"]["hash"]);
+        });
+    }
+}
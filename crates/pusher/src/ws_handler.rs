@@ -1,16 +1,20 @@
 use actix::prelude::*;
 use actix::{Actor, Addr, AsyncContext, Running};
 use actix_web_actors::ws;
-use actix_web_actors::ws::{ProtocolError, WebsocketContext};
+use actix_web_actors::ws::{CloseCode, CloseReason, ProtocolError, WebsocketContext};
 
 use std::time::{Duration, Instant};
 
+use log::{debug, warn};
+
 use serde::Deserialize;
 
+use crate::kind::Event;
 use crate::messages::{PusherMessage, PusherMessageData};
 use crate::{OutgoingMessage, WebSocket};
 
-use crate::ws::{Connect, Disconnect, MessageWrapper, WebSocketHandler};
+use crate::ws::errors::{ErrorKind, WsError};
+use crate::ws::{ClientEvent, Connect, Disconnect, MessageWrapper, Terminate, WebSocketHandler};
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
@@ -21,36 +25,30 @@ pub struct Session {
     pub hb: Instant,
     addr: Addr<WebSocketHandler>,
     app_id: i64,
+    origin: Option<String>,
 }
 
 impl Session {
-    pub fn new(app_id: i64, addr: Addr<WebSocketHandler>) -> Self {
+    pub fn new(app_id: i64, origin: Option<String>, addr: Addr<WebSocketHandler>) -> Self {
         Self {
             id: 0,
             hb: Instant::now(),
             app_id,
+            origin,
             addr,
         }
     }
 
     fn start_hb(&self, ctx: &mut WebsocketContext<Self>) {
-        let app_id = self.app_id;
         ctx.run_interval(HEARTBEAT_INTERVAL, move |act, ctx| {
             // check client heartbeats
             if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
                 // heartbeat timed out
-                println!("Websocket Client heartbeat failed, disconnecting!");
-
-                // notify chat server
-                act.addr.do_send(Disconnect {
-                    id: act.id,
-                    app_id: app_id,
-                });
+                warn!("websocket {} missed its heartbeat, disconnecting", act.id);
 
-                println!("stopping because missed heartbeat");
-
-                // stop actor
-                ctx.stop();
+                // close with 4201 so the client reconnects, this also stops the
+                // actor and `stopping` notifies the handler
+                act.error(&ErrorKind::PongNotReceived, ctx);
 
                 // don't try to send a ping
                 return;
@@ -59,6 +57,27 @@ impl Session {
             ctx.ping(b"");
         });
     }
+
+    /// Sends `error` to the client as a `pusher:error` and closes the
+    /// connection with its close code when it has one.
+    fn error(&self, error: &dyn WsError, ctx: &mut WebsocketContext<Self>) {
+        if let Some(msg) = error.to_msg() {
+            ctx.text(msg);
+        }
+
+        if let Some(code) = error.close_code() {
+            if error.is_fatal() {
+                debug!("websocket {} closed for good: {}", self.id, error);
+            }
+
+            ctx.close(Some(CloseReason {
+                code: CloseCode::Other(code),
+                description: Some(error.to_string()),
+            }));
+
+            ctx.stop();
+        }
+    }
 }
 
 impl Actor for Session {
@@ -76,18 +95,17 @@ impl Actor for Session {
                     id: self.id,
                     app_id: self.app_id,
                     channels: vec![],
-                    conn: address.recipient(),
+                    conn: address.clone().recipient(),
                 },
+                origin: self.origin.clone(),
+                terminate: address.recipient(),
             })
             .into_actor(self)
             .then(|res, act, ctx| {
                 match res {
                     Ok(res) => match res {
                         Ok(id) => act.id = id,
-                        Err(e) => {
-                            println!("{:?}", e);
-                            ctx.stop();
-                        }
+                        Err(e) => act.error(e.as_ref(), ctx),
                     },
                     _ => ctx.stop(),
                 }
@@ -114,15 +132,45 @@ impl Handler<OutgoingMessage> for Session {
     }
 }
 
+impl Handler<Terminate> for Session {
+    type Result = ();
+
+    fn handle(&mut self, msg: Terminate, ctx: &mut Self::Context) -> Self::Result {
+        self.error(&msg.error, ctx);
+    }
+}
+
 impl StreamHandler<Result<ws::Message, ProtocolError>> for Session {
     fn handle(&mut self, item: Result<ws::Message, ProtocolError>, ctx: &mut Self::Context) {
         match item {
             Ok(msg) => {
                 match msg {
                     ws::Message::Text(txt) => {
-                        let message: IncomingMessage = serde_json::from_str(&txt).unwrap();
-
-                        if let MessageData::Other(data) = message.data {
+                        let message: IncomingMessage = match serde_json::from_str(&txt) {
+                            Ok(message) => message,
+                            Err(e) => {
+                                debug!("websocket {} sent a malformed frame: {}", self.id, e);
+                                self.error(&ErrorKind::InvalidMessage, ctx);
+                                return;
+                            }
+                        };
+
+                        if let Event::Client(event) = Event::from(message.event.clone()) {
+                            self.addr.do_send(ClientEvent {
+                                ws: WebSocket {
+                                    channels: vec![],
+                                    presence_data: None,
+                                    conn: ctx.address().recipient(),
+                                    id: self.id,
+                                    app_id: self.app_id,
+                                },
+                                event,
+                                channel: message.channel,
+                                data: message.data,
+                            });
+                        } else if let Ok(MessageData::Other(data)) =
+                            serde_json::from_value::<MessageData>(message.data)
+                        {
                             let pusher_message = MessageWrapper {
                                 message: PusherMessage {
                                     data,
@@ -141,11 +189,11 @@ impl StreamHandler<Result<ws::Message, ProtocolError>> for Session {
                             self.addr
                                 .send(pusher_message)
                                 .into_actor(self)
-                                .then(|res, _, ctx| {
+                                .then(|res, act, ctx| {
                                     match res {
                                         Ok(res) => match res {
                                             Ok(_) => (),
-                                            Err(_e) => ctx.stop(),
+                                            Err(e) => act.error(e.as_ref(), ctx),
                                         },
                                         _ => ctx.stop(),
                                     };
@@ -185,7 +233,9 @@ impl StreamHandler<Result<ws::Message, ProtocolError>> for Session {
 struct IncomingMessage {
     name: Option<String>,
     event: Option<String>,
-    data: MessageData,
+    channel: Option<String>,
+    #[serde(default)]
+    data: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]